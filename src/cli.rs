@@ -0,0 +1,153 @@
+use crate::{
+  command::Output,
+  error::Error,
+  logger::Level,
+  summary, supervisor,
+  utils::{argv, duration},
+};
+use std::{collections::HashMap, time::Duration};
+
+#[derive(Debug, PartialEq)]
+pub enum Action {
+  List,
+  Run(String),
+  Show(String),
+  Clean(Option<String>),
+  Watch(String),
+  /// Logs of a run, of one of its tasks when given
+  Logs(Option<String>),
+}
+
+/// Parsed command line
+#[derive(Debug)]
+pub struct Cli {
+  pub action: Action,
+  pub variables: HashMap<String, String>,
+  pub dry_run: bool,
+  pub json: bool,
+  pub verbosity: Level,
+  pub grace_period: Duration,
+  pub timeout: Option<Duration>,
+  pub output: Option<Output>,
+  /// Run the logs are read from, the last one by default
+  pub run: Option<u64>,
+  pub follow: bool,
+  /// Printed at the end of a run, a table by default
+  pub summary: summary::Format,
+}
+
+impl Cli {
+  pub fn parse<I, S>(args: I) -> Result<Self, Error>
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    let args: Vec<String> = args.into_iter().map(|s| s.into()).collect();
+    let (params, vars) = argv::extract_vars(args.iter().map(|s| s.as_str()));
+
+    let mut variables: HashMap<String, String> = HashMap::new();
+    for (key, value) in vars {
+      variables.insert(key.to_string(), value.to_string());
+    }
+
+    let mut dry_run = false;
+    let mut json = false;
+    let mut verbosity = Level::Normal;
+    let mut grace_period = supervisor::DEFAULT_GRACE_PERIOD;
+    let mut timeout = None;
+    let mut output = None;
+    let mut run = None;
+    let mut follow = false;
+    let mut summary = summary::Format::default();
+
+    let mut positionals: Vec<&str> = Vec::new();
+    let mut params = params.into_iter();
+    while let Some(param) = params.next() {
+      if !argv::is_option(param) {
+        positionals.push(param);
+        continue;
+      }
+
+      let (flag, given) = match param.split_once('=') {
+        Some((flag, value)) => (flag, Some(value)),
+        None => (param, None),
+      };
+      // Given as --flag=value or --flag value
+      let mut value = || option_value(flag, given.or_else(|| params.next()));
+
+      match flag {
+        "-n" | "--dry-run" => dry_run = true,
+        "--json" => json = true,
+        "-q" | "--quiet" => verbosity = Level::Quiet,
+        "-v" | "--verbose" => verbosity = Level::Verbose,
+        "-vv" => verbosity = Level::Debug,
+        "--grace-period" => grace_period = duration::parse(value()?)?,
+        "--timeout" => timeout = Some(duration::parse(value()?)?),
+        "--output" => output = Some(value()?.parse()?),
+        "--run" => run = Some(run_id(value()?)?),
+        "-f" | "--follow" => follow = true,
+        "--summary" => summary = value()?.parse()?,
+        // A variable given an empty value, like --var.suffix=
+        _ if argv::is_var_option(flag) && given == Some("") => {
+          if let Some((_, key)) = argv::get_var_option(flag) {
+            variables.insert(key.to_string(), String::new());
+          }
+        }
+        _ => return Err(Error::Command(format!("Unknown option \"{}\"", flag))),
+      }
+    }
+
+    if json && !dry_run {
+      let err = "\"--json\" prints the plan, use it with --dry-run".to_string();
+      return Err(Error::Command(err));
+    }
+
+    let mut params = positionals.into_iter();
+    let action = match params.next() {
+      None => Action::List,
+      Some("show") => Action::Show(task_param("show", params.next())?),
+      Some("run") => Action::Run(task_param("run", params.next())?),
+      Some("watch") => Action::Watch(task_param("watch", params.next())?),
+      Some("clean") => Action::Clean(params.next().map(|name| name.to_string())),
+      Some("logs") => Action::Logs(params.next().map(|name| name.to_string())),
+      Some(name) => Action::Run(name.to_string()),
+    };
+
+    Ok(Self {
+      action,
+      variables,
+      dry_run,
+      json,
+      verbosity,
+      grace_period,
+      timeout,
+      output,
+      run,
+      follow,
+      summary,
+    })
+  }
+}
+
+fn task_param(action: &str, param: Option<&str>) -> Result<String, Error> {
+  match param {
+    Some(name) => Ok(name.to_string()),
+    None => Err(Error::Command(format!(
+      "\"{}\" expects a task name",
+      action
+    ))),
+  }
+}
+
+fn option_value<'a>(flag: &str, value: Option<&'a str>) -> Result<&'a str, Error> {
+  match value {
+    Some(value) => Ok(value),
+    None => Err(Error::Command(format!("\"{}\" expects a value", flag))),
+  }
+}
+
+fn run_id(value: &str) -> Result<u64, Error> {
+  value
+    .parse()
+    .map_err(|_| Error::Command(format!("\"--run\" expects a run number, got \"{}\"", value)))
+}
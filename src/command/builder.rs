@@ -1,24 +1,63 @@
-use super::command::Command;
-use crate::error::Error;
+use super::{
+  command::{Command, Invocation},
+  condition::Condition,
+  dependency::Dependency,
+  export::Export,
+  hooks::Hooks,
+  inspect::Inspection,
+  origin::{builtin_variables, Origin},
+  output::Output,
+  retry::Retry,
+  service::Probe,
+  shell::Shell,
+};
+use crate::{
+  error::Error,
+  utils::{argv, platform::Platforms},
+};
 use std::{
   collections::HashMap,
   env,
   path::PathBuf,
   str::FromStr,
+  time::Duration,
 };
 
 #[derive(Debug, Clone)]
 pub struct CommandBuilder {
   cwd: Option<PathBuf>,
   args: Vec<String>,
+  // Command line of the arguments, split again when run without a shell
+  line: String,
   name: String,
-  shell: Option<PathBuf>,
+  shell: Option<Shell>,
   hidden: bool,
   source: PathBuf,
   pub(crate) variables: HashMap<String, String>,
   pub(crate) environments: HashMap<String, String>,
+  variable_origins: HashMap<String, Origin>,
+  environment_origins: HashMap<String, Origin>,
   description: Option<String>,
-  dependencies: Vec<String>,
+  dependencies: Vec<Dependency>,
+  timeout: Option<Duration>,
+  platforms: Platforms,
+  retry: Option<Retry>,
+  when: Option<Condition>,
+  sources: Vec<String>,
+  outputs: Vec<String>,
+  watch: Vec<String>,
+  service: bool,
+  ready: Option<Probe>,
+  matrix: Vec<(String, Vec<String>)>,
+  /// Variables of the matrix combination the task is an instance of, kept over the given ones
+  combination: Vec<(String, String)>,
+  parallel: bool,
+  hooks: Hooks,
+  script: Option<String>,
+  interpreter: Option<String>,
+  exec: Option<Vec<String>>,
+  output: Option<Output>,
+  exports: Vec<(String, Export)>,
 }
 
 impl CommandBuilder {
@@ -26,14 +65,35 @@ impl CommandBuilder {
     Self {
       cwd: None,
       args: Vec::new(),
+      line: String::new(),
       name: String::from("command"),
       shell: None,
       hidden: false,
       source: PathBuf::new(),
       variables: HashMap::new(),
       environments: HashMap::new(),
+      variable_origins: HashMap::new(),
+      environment_origins: HashMap::new(),
       description: None,
       dependencies: Vec::new(),
+      timeout: None,
+      platforms: Platforms::default(),
+      retry: None,
+      when: None,
+      sources: Vec::new(),
+      outputs: Vec::new(),
+      watch: Vec::new(),
+      service: false,
+      ready: None,
+      matrix: Vec::new(),
+      combination: Vec::new(),
+      parallel: false,
+      hooks: Hooks::default(),
+      script: None,
+      interpreter: None,
+      exec: None,
+      output: None,
+      exports: Vec::new(),
     }
   }
 
@@ -45,6 +105,7 @@ impl CommandBuilder {
     self
   }
 
+  /// Run the command line, in place of any command, script or exec set before
   pub fn with_command<S>(&mut self, command: S) -> &mut Self
   where
     S: Into<String>,
@@ -59,6 +120,9 @@ impl CommandBuilder {
 
     self.args.clear();
     self.args.extend(parameters);
+    self.line = cmd;
+    self.script = None;
+    self.exec = None;
 
     self
   }
@@ -92,17 +156,239 @@ impl CommandBuilder {
     self
   }
 
+  /// A program runs with its preset arguments, see `Shell::preset`
   pub fn with_shell<S>(&mut self, shell: S) -> &mut Self
   where
-    S: Into<PathBuf>,
+    S: Into<Shell>,
   {
     self.shell = Some(shell.into());
     self
   }
 
-  pub fn with_dependency<S>(&mut self, dependency: S) -> &mut Self
+  pub fn with_timeout(&mut self, timeout: Duration) -> &mut Self {
+    self.timeout = Some(timeout);
+    self
+  }
+
+  pub fn timeout(&self) -> Option<Duration> {
+    self.timeout
+  }
+
+  pub fn with_retry(&mut self, retry: Retry) -> &mut Self {
+    self.retry = Some(retry);
+    self
+  }
+
+  /// Run the task only when the condition is met
+  pub fn with_condition(&mut self, condition: Condition) -> &mut Self {
+    self.when = Some(condition);
+    self
+  }
+
+  /// Files hashed to decide whether the task is up to date
+  pub fn with_sources<I, S>(&mut self, sources: I) -> &mut Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    self.sources = sources.into_iter().map(|s| s.into()).collect();
+    self
+  }
+
+  /// Files produced by the task, they have to exist for the task to be up to date
+  pub fn with_outputs<I, S>(&mut self, outputs: I) -> &mut Self
   where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    self.outputs = outputs.into_iter().map(|s| s.into()).collect();
+    self
+  }
+
+  /// Files watched by `wk watch`, the sources are watched when empty
+  pub fn with_watch<I, S>(&mut self, watch: I) -> &mut Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    self.watch = watch.into_iter().map(|s| s.into()).collect();
+    self
+  }
+
+  /// Keep the task running in the background, its dependents start once it is ready
+  pub fn with_service(&mut self, service: bool) -> &mut Self {
+    self.service = service;
+    self
+  }
+
+  pub fn with_ready(&mut self, probe: Probe) -> &mut Self {
+    self.ready = Some(probe);
+    self
+  }
+
+  /// Spawn the program directly with this exact argv, without a shell
+  pub fn with_exec<I, S>(&mut self, argv: I) -> &mut Self
+  where
+    I: IntoIterator<Item = S>,
     S: Into<String>,
+  {
+    self.exec = Some(argv.into_iter().map(|s| s.into()).collect());
+    self.args.clear();
+    self.line.clear();
+    self
+  }
+
+  pub fn shell(&self) -> Option<&Shell> {
+    self.shell.as_ref()
+  }
+
+  pub fn exec(&self) -> Option<&Vec<String>> {
+    self.exec.as_ref()
+  }
+
+  /// Spawn the command directly, its words are split like a shell would
+  pub fn without_shell(&mut self) -> &mut Self {
+    if self.exec.is_none() {
+      let argv = argv::split(&self.line);
+      self.with_exec(argv);
+    }
+    self
+  }
+
+  /// Run a multi-line script from a temporary file instead of the command
+  pub fn with_script<S>(&mut self, script: S) -> &mut Self
+  where
+    S: Into<String>,
+  {
+    self.script = Some(script.into());
+    self
+  }
+
+  /// Program running the script, like `python3` or `bash -euo pipefail`. The shell by default
+  pub fn with_interpreter<S>(&mut self, interpreter: S) -> &mut Self
+  where
+    S: Into<String>,
+  {
+    self.interpreter = Some(interpreter.into());
+    self
+  }
+
+  pub fn with_output(&mut self, output: Output) -> &mut Self {
+    self.output = Some(output);
+    self
+  }
+
+  pub fn output(&self) -> Option<Output> {
+    self.output
+  }
+
+  /// Read a variable from the stdout, for the tasks depending on this one
+  pub fn with_export<S>(&mut self, name: S, export: Export) -> &mut Self
+  where
+    S: Into<String>,
+  {
+    let name = name.into();
+    self.exports.retain(|(key, _)| *key != name);
+    self.exports.push((name, export));
+    self.exports.sort_by(|a, b| a.0.cmp(&b.0));
+    self
+  }
+
+  pub fn with_hooks(&mut self, hooks: Hooks) -> &mut Self {
+    self.hooks = hooks;
+    self
+  }
+
+  /// Wrap the task with the hooks of its file
+  pub fn wrap_hooks(&mut self, hooks: &Hooks) -> &mut Self {
+    self.hooks.wrap(hooks);
+    self
+  }
+
+  /// Generate one instance of the task per combination of the variable values
+  pub fn with_matrix(&mut self, matrix: HashMap<String, Vec<String>>) -> &mut Self {
+    self.matrix = matrix.into_iter().collect();
+    self.matrix.sort();
+    self
+  }
+
+  /// Run the instances of the matrix together instead of one after the other
+  pub fn with_parallel(&mut self, parallel: bool) -> &mut Self {
+    self.parallel = parallel;
+    self
+  }
+
+  pub fn is_parallel(&self) -> bool {
+    self.parallel
+  }
+
+  pub fn has_matrix(&self) -> bool {
+    !self.matrix.is_empty()
+  }
+
+  /// Variables of each combination of the matrix, in the order of the keys
+  pub fn matrix_combinations(&self) -> Vec<Vec<(String, String)>> {
+    let mut combinations: Vec<Vec<(String, String)>> = vec![Vec::new()];
+    for (key, values) in self.matrix.iter() {
+      combinations = combinations
+        .into_iter()
+        .flat_map(|combination| {
+          values.iter().map(move |value| {
+            let mut combination = combination.clone();
+            combination.push((key.clone(), value.clone()));
+            combination
+          })
+        })
+        .collect();
+    }
+    combinations
+  }
+
+  /// Name of the instance of a matrix combination, like `build[environment=tool,target=chrome]`
+  pub fn matrix_name(&self, combination: &[(String, String)]) -> String {
+    let variables: Vec<String> = combination
+      .iter()
+      .map(|(key, value)| format!("{}={}", key, value))
+      .collect();
+    format!("{}[{}]", self.name, variables.join(","))
+  }
+
+  /// The task for one combination of the matrix, its name and its variables always agree
+  pub fn matrix_instance(&self, combination: &[(String, String)]) -> CommandBuilder {
+    let mut instance = self.clone();
+    instance.matrix.clear();
+    instance.combination = combination.to_vec();
+    instance
+      .with_name(self.matrix_name(combination))
+      .with_hidden(true);
+    instance
+  }
+
+  /// Whether the variables select this matrix instance, they give none of its keys another value
+  pub fn matches_combination(&self, variables: &HashMap<String, String>) -> bool {
+    self
+      .combination
+      .iter()
+      .all(|(key, value)| variables.get(key).is_none_or(|v| v == value))
+  }
+
+  /// Restrict the task to a list of platforms, every list added has to match
+  pub fn with_platforms<I, S>(&mut self, platforms: I) -> &mut Self
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    self.platforms.add(platforms);
+    self
+  }
+
+  pub fn platforms(&self) -> &Platforms {
+    &self.platforms
+  }
+
+  pub fn with_dependency<S>(&mut self, dependency: S) -> &mut Self
+  where
+    S: Into<Dependency>,
   {
     self.dependencies.push(dependency.into());
     self
@@ -111,7 +397,7 @@ impl CommandBuilder {
   pub fn with_dependencies<I, S>(&mut self, dependencies: I) -> &mut Self
   where
     I: IntoIterator<Item = S>,
-    S: Into<String>,
+    S: Into<Dependency>,
   {
     for dependency in dependencies {
       self.with_dependency(dependency);
@@ -123,7 +409,12 @@ impl CommandBuilder {
   where
     S: Into<String>,
   {
-    self.args.push(arg.into());
+    let arg = arg.into();
+    if !self.line.is_empty() {
+      self.line.push(' ');
+    }
+    self.line.push_str(&argv::quote(&arg));
+    self.args.push(arg);
     self
   }
 
@@ -144,6 +435,7 @@ impl CommandBuilder {
     S: Into<String>,
   {
     self.args.clear();
+    self.line.clear();
     for arg in args {
       self.with_arg(arg);
     }
@@ -151,42 +443,144 @@ impl CommandBuilder {
   }
 
   pub fn with_variables(&mut self, variables: HashMap<String, String>) -> &mut Self {
-    self.variables.extend(variables);
+    let origin = Origin::Task(self.name.clone());
+    self.with_variables_from(variables, origin)
+  }
+
+  pub fn with_variables_from(
+    &mut self,
+    variables: HashMap<String, String>,
+    origin: Origin,
+  ) -> &mut Self {
+    for (key, value) in variables {
+      self.variable_origins.insert(key.clone(), origin.clone());
+      self.variables.insert(key, value);
+    }
     self
   }
 
   pub fn with_environments(&mut self, environments: HashMap<String, String>) -> &mut Self {
-    self.environments.extend(environments);
+    let origin = Origin::Task(self.name.clone());
+    self.with_environments_from(environments, origin)
+  }
+
+  pub fn with_environments_from(
+    &mut self,
+    environments: HashMap<String, String>,
+    origin: Origin,
+  ) -> &mut Self {
+    for (key, value) in environments {
+      self.environment_origins.insert(key.clone(), origin.clone());
+      self.environments.insert(key, value);
+    }
     self
   }
 
-  pub fn to_command(&self, variables: Option<&HashMap<String, String>>) -> Command {
-    // Set variables
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn description(&self) -> Option<&str> {
+    self.description.as_deref()
+  }
+
+  pub fn source(&self) -> &PathBuf {
+    &self.source
+  }
+
+  /// Merge built-in, task and command-line variables, keeping track of their origin
+  pub fn resolve_variables(
+    &self,
+    variables: Option<&HashMap<String, String>>,
+  ) -> HashMap<String, (String, Origin)> {
     let mut vars = HashMap::new();
-    vars.extend(&self.variables);
+
+    for (key, value) in builtin_variables() {
+      vars.insert(key, (value, Origin::Builtin));
+    }
+
+    for (key, value) in self.variables.iter() {
+      let origin = self
+        .variable_origins
+        .get(key)
+        .cloned()
+        .unwrap_or_else(|| Origin::Task(self.name.clone()));
+      vars.insert(key.clone(), (value.clone(), origin));
+    }
+
     if let Some(v) = variables {
-      vars.extend(v);
+      for (key, value) in v.iter() {
+        vars.insert(key.clone(), (value.clone(), Origin::Cli));
+      }
     }
 
+    for (key, value) in self.combination.iter() {
+      let origin = Origin::Task(self.name.clone());
+      vars.insert(key.clone(), (value.clone(), origin));
+    }
+
+    vars
+  }
+
+  pub fn resolve_environments(&self) -> HashMap<String, (String, Origin)> {
+    let mut envs = HashMap::new();
+
+    for (key, value) in self.environments.iter() {
+      let origin = self
+        .environment_origins
+        .get(key)
+        .cloned()
+        .unwrap_or_else(|| Origin::Task(self.name.clone()));
+      envs.insert(key.clone(), (value.clone(), origin));
+    }
+
+    envs
+  }
+
+  pub fn inspect(&self, variables: Option<&HashMap<String, String>>) -> Inspection<'_> {
+    let mut vars: Vec<(String, String, Origin)> = self
+      .resolve_variables(variables)
+      .into_iter()
+      .map(|(key, (value, origin))| (key, value, origin))
+      .collect();
+    vars.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut envs: Vec<(String, String, Origin)> = self
+      .resolve_environments()
+      .into_iter()
+      .map(|(key, (value, origin))| (key, value, origin))
+      .collect();
+    envs.sort_by(|a, b| a.0.cmp(&b.0));
+
+    Inspection {
+      command: self.to_command(variables),
+      description: self.description(),
+      source: &self.source,
+      order: Vec::new(),
+      variables: vars,
+      environments: envs,
+    }
+  }
+
+  pub fn to_command(&self, variables: Option<&HashMap<String, String>>) -> Command {
+    // Set variables
+    let vars: HashMap<String, String> = self
+      .resolve_variables(variables)
+      .into_iter()
+      .map(|(key, (value, _))| (key, value))
+      .collect();
+
     // Set arguments
-    let mut args: Vec<String> = self
+    let args: Vec<String> = self
       .args
       .iter()
-      .map(|arg: &String| {
-        let mut arg_res = arg.to_string();
-
-        for (key, value) in vars.iter() {
-          let r_key = format!("${{{}}}", key);
-          arg_res = arg_res.as_str().replace(r_key.as_str(), value);
-        }
-
-        arg_res
-      })
+      .map(|arg: &String| replace_variables(arg, &vars))
       .collect();
 
     // Set CWD
     let mut cwd: Option<PathBuf> = None;
     if let Some(ccwd) = &self.cwd {
+      let ccwd = replace_variables(&ccwd.to_string_lossy(), &vars);
       cwd = Some(PathBuf::new().join(ccwd));
     } else if let Ok(ccwd) = env::current_dir() {
       cwd = Some(ccwd);
@@ -199,32 +593,75 @@ impl CommandBuilder {
     }
 
     // Set Shell
-    let shell = {
-      if let Some(shell) = &self.shell {
-        args.insert(0, "-c".into());
-        PathBuf::new().join(shell)
-      } else {
-        if cfg!(windows) {
-          args.insert(0, "/c".into());
-          PathBuf::new().join("cmd.exe")
-        } else {
-          args.insert(0, "-c".into());
-          PathBuf::new().join("bash")
-        }
-      }
+    let shell = self.shell.clone().unwrap_or_default();
+
+    // Set interpreter of the script
+    let interpreter: Vec<String> = match &self.interpreter {
+      Some(interpreter) => replace_variables(interpreter, &vars)
+        .split_whitespace()
+        .map(|word| word.to_string())
+        .collect(),
+      None => shell.interpreter(),
     };
 
+    // Set argv of a direct exec, arguments come after the program ones
+    let exec: Option<Vec<String>> = self.exec.as_ref().map(|exec| {
+      exec
+        .iter()
+        .chain(self.args.iter())
+        .map(|arg| replace_variables(arg, &vars))
+        .collect()
+    });
+
     Command {
-      name: &self.name,
+      name: self.name.clone(),
       cwd,
       args,
-      shell,
-      environments: &self.environments,
-      dependencies: &self.dependencies,
+      shell: shell.program,
+      shell_args: shell.args,
+      environments: self.environments.clone(),
+      dependencies: self.dependencies.clone(),
+      arguments: Vec::new(),
+      group: None,
+      series: None,
+      timeout: self.timeout,
+      retry: self.retry.clone(),
+      when: self.when.clone(),
+      source: self.source.clone(),
+      sources: self.sources.iter().map(|s| replace_variables(s, &vars)).collect(),
+      outputs: self.outputs.iter().map(|s| replace_variables(s, &vars)).collect(),
+      watch: self.watch.iter().map(|s| replace_variables(s, &vars)).collect(),
+      service: self.service,
+      ready: self.ready.clone(),
+      hooks: self.hooks.replace_variables(&vars),
+      script: self.script.as_ref().map(|s| replace_variables(s, &vars)),
+      interpreter,
+      exec,
+      output: self.output,
+      exports: self.exports.clone(),
+      invocation: Invocation {
+        dependency: Dependency::new(&self.name),
+        entry: None,
+        variables: variables.cloned().unwrap_or_default(),
+      },
+      log: None,
+      variables: vars,
     }
   }
 }
 
+/// Replace every `${key}` of the string with its value
+pub fn replace_variables(s: &str, variables: &HashMap<String, String>) -> String {
+  let mut res = s.to_string();
+
+  for (key, value) in variables.iter() {
+    let r_key = format!("${{{}}}", key);
+    res = res.as_str().replace(r_key.as_str(), value);
+  }
+
+  res
+}
+
 impl FromStr for CommandBuilder {
   type Err = Error;
 
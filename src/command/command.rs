@@ -1,56 +1,212 @@
-use super::future::CommandFuture;
-use std::{collections::HashMap, fmt, path::PathBuf};
+use super::{
+  condition::Condition, dependency::Dependency, export::Export, future::CommandFuture,
+  hooks::Hooks, output::Output, retry::Retry, service::Probe,
+};
+use crate::{logs::TaskLog, utils::argv::quote};
+use std::{collections::HashMap, fmt, path::PathBuf, time::Duration};
 
-#[derive(Debug)]
-pub struct Command<'a> {
-  pub name: &'a str,
+#[derive(Debug, Clone)]
+pub struct Command {
+  pub name: String,
   pub cwd: Option<PathBuf>,
   pub args: Vec<String>,
   pub shell: PathBuf,
-  pub dependencies: &'a Vec<String>,
-  pub environments: &'a HashMap<String, String>,
+  /// Arguments of the shell, put before the command
+  pub shell_args: Vec<String>,
+  pub dependencies: Vec<Dependency>,
+  /// Arguments and variables given by the dependency that created the command
+  pub arguments: Vec<String>,
+  pub environments: HashMap<String, String>,
+  pub variables: HashMap<String, String>,
+  /// Concurrent task the command runs with
+  pub group: Option<String>,
+  /// Series task the command is a step of
+  pub series: Option<String>,
+  pub timeout: Option<Duration>,
+  pub retry: Option<Retry>,
+  pub when: Option<Condition>,
+  /// File the task has been declared in
+  pub source: PathBuf,
+  pub sources: Vec<String>,
+  pub outputs: Vec<String>,
+  pub watch: Vec<String>,
+  /// Runs in the background until the end of the run, its dependents start once it is ready
+  pub service: bool,
+  pub ready: Option<Probe>,
+  pub hooks: Hooks,
+  /// Script run from a temporary file instead of the command line
+  pub script: Option<String>,
+  /// Program and arguments the script file is given to
+  pub interpreter: Vec<String>,
+  /// Argv spawned directly, without a shell
+  pub exec: Option<Vec<String>>,
+  /// How the output is shown, see `output_mode`
+  pub output: Option<Output>,
+  /// Variables read from the stdout, given to the tasks depending on it
+  pub exports: Vec<(String, Export)>,
+  pub invocation: Invocation,
+  /// File the stdout and stderr are logged to, during a run
+  pub log: Option<TaskLog>,
 }
 
-impl<'a> Command<'a> {
-  pub fn execute(mut self) -> CommandFuture {
-    CommandFuture::new(&mut self)
+/// What created a command, to resolve it again once more variables are known
+#[derive(Debug, Clone)]
+pub struct Invocation {
+  pub dependency: Dependency,
+  /// Group invocation and index of the entry the command comes from
+  pub entry: Option<(Dependency, usize)>,
+  /// Variables given to the task
+  pub variables: HashMap<String, String>,
+}
+
+impl Command {
+  /// Same task, spawned with the same arguments and resolved variables
+  pub fn is_same_invocation(&self, other: &Command) -> bool {
+    self.name == other.name && self.args == other.args && self.variables == other.variables
   }
 
-  pub fn debug(&self) {
-    print!("\n\n");
+  /// Name of the task with the arguments of its instance
+  pub fn label(&self) -> String {
+    let mut label = self.name.clone();
+    for argument in self.arguments.iter() {
+      label.push(' ');
+      label.push_str(argument);
+    }
+    label
+  }
 
-    if let Some(cwd) = &self.cwd {
-      print!("\nFrom: {} ", cwd.to_string_lossy());
+  /// Group or series invocation the command is an entry of, with its arguments
+  pub fn group_label(&self) -> Option<String> {
+    let entry = self.invocation.entry.as_ref();
+    entry.map(|(group, _)| group.to_string())
+  }
+
+  /// Output of the command, prefixed by default when it runs along other tasks
+  pub fn output_mode(&self) -> Output {
+    match self.output {
+      Some(output) => output,
+      None if self.group.is_some() || self.service => Output::Prefixed,
+      None => Output::Inherit,
     }
+  }
 
-    print!("with {}\n", self.shell.to_string_lossy());
-    println!("Run {}\n", self.name);
+  /// Whether the command runs alone, so that it can read from the terminal
+  pub fn is_foreground(&self) -> bool {
+    self.group.is_none() && !self.service
   }
 
-  pub fn display(&self) {
-    println!("{}", self);
+  pub fn execute(&self) -> CommandFuture {
+    CommandFuture::new(self)
+  }
+
+  /// Program and arguments as they are given to the process.
+  /// A script is given to its interpreter as a file, it stands in for the file here.
+  pub fn argv(&self) -> Vec<String> {
+    if let Some(script) = &self.script {
+      let mut argv = self.interpreter.clone();
+      argv.push(script.clone());
+      return argv;
+    }
+
+    if let Some(exec) = &self.exec {
+      return exec.clone();
+    }
+
+    self.argv_for(&self.args.join(" "))
+  }
+
+  /// Argv running another script with the shell of the command
+  pub fn argv_for(&self, script: &str) -> Vec<String> {
+    let mut argv = vec![self.shell.to_string_lossy().to_string()];
+    argv.extend(self.shell_args.iter().cloned());
+    argv.push(script.to_string());
+    argv
+  }
+
+  /// Whether the condition of the command is met, if any
+  pub fn is_enabled(&self) -> bool {
+    match &self.when {
+      Some(condition) => condition.evaluate(self),
+      None => true,
+    }
   }
 }
 
-impl<'a> std::fmt::Display for Command<'a> {
+impl std::fmt::Display for Command {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    write!(f, "\n\n")?;
-    write!(f, "Run: {}\n", self.name)?;
+    writeln!(f, "Run: {}", self.label())?;
+    if let Some(group) = self.group_label() {
+      match self.series {
+        Some(_) => writeln!(f, "In series: {}", group)?,
+        None => writeln!(f, "Concurrently with: {}", group)?,
+      }
+    }
     write!(f, "Dependencies: ")?;
-    write!(f, "{}\n", self.dependencies.join(", "))?;
+    let dependencies: Vec<String> = self.dependencies.iter().map(|d| d.to_string()).collect();
+    writeln!(f, "{}", dependencies.join(", "))?;
+
+    let mut environments: Vec<(&String, &String)> = self.environments.iter().collect();
+    environments.sort();
 
     write!(f, "Environments:")?;
-    for (key, value) in self.environments {
-      write!(f, " {}={}", key, value)?;
+    for (key, value) in environments {
+      write!(f, " {}={}", key, quote(value))?;
     }
-    write!(f, "\n")?;
+    writeln!(f)?;
 
     if let Some(cwd) = &self.cwd {
       writeln!(f, "From: {}", cwd.to_string_lossy())?;
     }
 
-    writeln!(f, "Shell: {}", self.shell.to_string_lossy())?;
-    writeln!(f, "Command: {:?}", self.args.join(" "))?;
+    match &self.exec {
+      Some(_) => writeln!(f, "Shell: none")?,
+      None => writeln!(f, "Shell: {}", self.shell.to_string_lossy())?,
+    }
+    if let Some(timeout) = self.timeout {
+      writeln!(f, "Timeout: {:?}", timeout)?;
+    }
+    if let Some(retry) = &self.retry {
+      writeln!(f, "Retry: {} attempt(s)", retry.attempts)?;
+    }
+    if self.service {
+      match &self.ready {
+        Some(probe) => writeln!(f, "Service: ready when {}", probe)?,
+        None => writeln!(f, "Service: ready once started")?,
+      }
+    }
+    if self.output_mode() != Output::Inherit {
+      writeln!(f, "Output: {}", self.output_mode())?;
+    }
+    if let Some(when) = &self.when {
+      writeln!(f, "When: {}", when)?;
+    }
+    if !self.sources.is_empty() {
+      writeln!(f, "Sources: {}", self.sources.join(", "))?;
+    }
+    if !self.outputs.is_empty() {
+      writeln!(f, "Outputs: {}", self.outputs.join(", "))?;
+    }
+    if !self.exports.is_empty() {
+      let exports: Vec<String> = self
+        .exports
+        .iter()
+        .map(|(name, export)| format!("{} from {}", name, export))
+        .collect();
+      writeln!(f, "Exports: {}", exports.join(", "))?;
+    }
+    write!(f, "{}", self.hooks)?;
+
+    if let Some(script) = &self.script {
+      writeln!(f, "Interpreter: {}", self.interpreter.join(" "))?;
+      writeln!(f, "Script:")?;
+      for line in script.lines() {
+        writeln!(f, "  {}", line)?;
+      }
+      return Ok(());
+    }
+
+    let argv: Vec<String> = self.argv().iter().map(|arg| quote(arg)).collect();
+    writeln!(f, "Command: {}", argv.join(" "))?;
 
     Ok(())
   }
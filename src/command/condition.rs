@@ -0,0 +1,303 @@
+use super::{builder::replace_variables, command::Command};
+use crate::error::Error;
+use std::{fmt, process::Stdio};
+
+/// Comparison of an environment or a variable value
+#[derive(Debug, Clone, PartialEq)]
+pub struct Compare {
+  pub equals: bool,
+  pub value: String,
+}
+
+/// Guard of a task, evaluated right before spawning it
+///
+/// ```text
+/// env(CI) && var(target) == chrome
+/// !exists(dist/index.js) || sh(git diff --quiet)
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+  Bool(bool),
+  Env(String, Option<Compare>),
+  Var(String, Option<Compare>),
+  Exists(String),
+  Shell(String),
+  Not(Box<Condition>),
+  And(Box<Condition>, Box<Condition>),
+  Or(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+  /// Evaluate against the resolved command, `${var}` are replaced in paths, scripts and values
+  pub fn evaluate(&self, command: &Command) -> bool {
+    let expand = |s: &str| replace_variables(s, &command.variables);
+    let compare = |c: &Option<Compare>| {
+      c.as_ref().map(|c| Compare {
+        equals: c.equals,
+        value: expand(&c.value),
+      })
+    };
+
+    match self {
+      Condition::Bool(b) => *b,
+      Condition::Env(name, c) => {
+        let value = match command.environments.get(name) {
+          Some(value) => Some(value.clone()),
+          None => std::env::var(name).ok(),
+        };
+        compare_value(value.as_deref(), &compare(c))
+      }
+      Condition::Var(name, c) => {
+        let value = command.variables.get(name).map(|v| v.as_str());
+        match c {
+          None => !matches!(value, None | Some("") | Some("false")),
+          Some(_) => compare_value(value, &compare(c)),
+        }
+      }
+      Condition::Exists(path) => match &command.cwd {
+        Some(cwd) => cwd.join(expand(path)).exists(),
+        None => std::path::Path::new(&expand(path)).exists(),
+      },
+      Condition::Shell(script) => {
+        let argv = command.argv_for(&expand(script));
+        let mut process = std::process::Command::new(&argv[0]);
+        process
+          .args(&argv[1..])
+          .envs(command.environments.iter())
+          .stdin(Stdio::null())
+          .stdout(Stdio::null())
+          .stderr(Stdio::null());
+
+        if let Some(cwd) = &command.cwd {
+          process.current_dir(cwd);
+        }
+
+        process.status().map(|s| s.success()).unwrap_or(false)
+      }
+      Condition::Not(c) => !c.evaluate(command),
+      Condition::And(a, b) => a.evaluate(command) && b.evaluate(command),
+      Condition::Or(a, b) => a.evaluate(command) || b.evaluate(command),
+    }
+  }
+
+  pub fn and(self, other: Condition) -> Condition {
+    Condition::And(Box::new(self), Box::new(other))
+  }
+}
+
+fn compare_value(value: Option<&str>, compare: &Option<Compare>) -> bool {
+  match (value, compare) {
+    (value, None) => value.is_some(),
+    (Some(value), Some(c)) => (value == c.value) == c.equals,
+    (None, Some(c)) => !c.equals,
+  }
+}
+
+impl std::str::FromStr for Condition {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut parser = Parser {
+      input: s,
+      position: 0,
+    };
+
+    let condition = parser.or()?;
+    parser.skip_whitespaces();
+    if parser.position < s.len() {
+      return Err(parser.error("unexpected input"));
+    }
+
+    Ok(condition)
+  }
+}
+
+impl std::fmt::Display for Condition {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let compare = |f: &mut fmt::Formatter<'_>, compare: &Option<Compare>| match compare {
+      Some(c) if c.equals => write!(f, " == {:?}", c.value),
+      Some(c) => write!(f, " != {:?}", c.value),
+      None => Ok(()),
+    };
+
+    match self {
+      Condition::Bool(b) => write!(f, "{}", b),
+      Condition::Env(name, c) => {
+        write!(f, "env({})", name)?;
+        compare(f, c)
+      }
+      Condition::Var(name, c) => {
+        write!(f, "var({})", name)?;
+        compare(f, c)
+      }
+      Condition::Exists(path) => write!(f, "exists({})", path),
+      Condition::Shell(cmd) => write!(f, "sh({})", cmd),
+      Condition::Not(c) => write!(f, "!({})", c),
+      Condition::And(a, b) => write!(f, "({} && {})", a, b),
+      Condition::Or(a, b) => write!(f, "({} || {})", a, b),
+    }
+  }
+}
+
+struct Parser<'a> {
+  input: &'a str,
+  position: usize,
+}
+
+impl<'a> Parser<'a> {
+  fn rest(&self) -> &'a str {
+    &self.input[self.position..]
+  }
+
+  fn error(&self, message: &str) -> Error {
+    Error::Import(format!(
+      "Invalid condition \"{}\": {} at {}",
+      self.input, message, self.position
+    ))
+  }
+
+  fn skip_whitespaces(&mut self) {
+    let rest = self.rest();
+    self.position += rest.len() - rest.trim_start().len();
+  }
+
+  fn eat(&mut self, token: &str) -> bool {
+    self.skip_whitespaces();
+    if self.rest().starts_with(token) {
+      self.position += token.len();
+      return true;
+    }
+    false
+  }
+
+  fn or(&mut self) -> Result<Condition, Error> {
+    let mut condition = self.and()?;
+    while self.eat("||") {
+      condition = Condition::Or(Box::new(condition), Box::new(self.and()?));
+    }
+    Ok(condition)
+  }
+
+  fn and(&mut self) -> Result<Condition, Error> {
+    let mut condition = self.unary()?;
+    while self.eat("&&") {
+      condition = Condition::And(Box::new(condition), Box::new(self.unary()?));
+    }
+    Ok(condition)
+  }
+
+  fn unary(&mut self) -> Result<Condition, Error> {
+    if self.rest().trim_start().starts_with("!=") {
+      return Err(self.error("unexpected \"!=\""));
+    }
+    if self.eat("!") {
+      return Ok(Condition::Not(Box::new(self.unary()?)));
+    }
+    self.primary()
+  }
+
+  fn primary(&mut self) -> Result<Condition, Error> {
+    if self.eat("(") {
+      let condition = self.or()?;
+      if !self.eat(")") {
+        return Err(self.error("expected \")\""));
+      }
+      return Ok(condition);
+    }
+
+    self.skip_whitespaces();
+    let name = self.word();
+    match name {
+      "true" => return Ok(Condition::Bool(true)),
+      "false" => return Ok(Condition::Bool(false)),
+      "" => return Err(self.error("expected a condition")),
+      _ => {}
+    }
+
+    if !self.eat("(") {
+      return Err(self.error("expected \"(\""));
+    }
+    let argument = self.argument()?;
+
+    match name {
+      "env" => Ok(Condition::Env(argument, self.compare()?)),
+      "var" => Ok(Condition::Var(argument, self.compare()?)),
+      "exists" => Ok(Condition::Exists(argument)),
+      "sh" => Ok(Condition::Shell(argument)),
+      _ => Err(self.error(&format!("unknown function \"{}\"", name))),
+    }
+  }
+
+  fn word(&mut self) -> &'a str {
+    let rest = self.rest();
+    let end = rest
+      .find(|c: char| !(c.is_alphanumeric() || "_-.:/${}@+".contains(c)))
+      .unwrap_or(rest.len());
+    self.position += end;
+    &rest[..end]
+  }
+
+  /// Raw content up to the matching closing parenthesis
+  fn argument(&mut self) -> Result<String, Error> {
+    let rest = self.rest();
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+
+    for (index, c) in rest.char_indices() {
+      match (c, quote) {
+        ('\'', None) | ('"', None) => quote = Some(c),
+        (c, Some(q)) if c == q => quote = None,
+        ('(', None) => depth += 1,
+        (')', None) if depth == 0 => {
+          self.position += index + 1;
+          return Ok(unquote(rest[..index].trim()));
+        }
+        (')', None) => depth -= 1,
+        _ => {}
+      }
+    }
+
+    Err(self.error("expected \")\""))
+  }
+
+  fn compare(&mut self) -> Result<Option<Compare>, Error> {
+    let equals = if self.eat("==") {
+      true
+    } else if self.eat("!=") {
+      false
+    } else {
+      return Ok(None);
+    };
+
+    self.skip_whitespaces();
+    let value = match self.rest().chars().next() {
+      Some(q) if q == '\'' || q == '"' => {
+        let rest = &self.rest()[1..];
+        match rest.find(q) {
+          Some(end) => {
+            self.position += end + 2;
+            rest[..end].to_string()
+          }
+          None => return Err(self.error("unterminated string")),
+        }
+      }
+      // A missing value is likely a typo, an empty one is written ""
+      _ => match self.word() {
+        "" => return Err(self.error("expected a value, \"\" for an empty one")),
+        word => word.to_string(),
+      },
+    };
+
+    Ok(Some(Compare { equals, value }))
+  }
+}
+
+fn unquote(s: &str) -> String {
+  let quoted = s.len() >= 2
+    && ((s.starts_with('"') && s.ends_with('"')) || (s.starts_with('\'') && s.ends_with('\'')));
+
+  if quoted {
+    return s[1..s.len() - 1].to_string();
+  }
+  s.to_string()
+}
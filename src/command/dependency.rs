@@ -0,0 +1,71 @@
+use crate::utils::argv;
+use std::{collections::HashMap, fmt};
+
+/// A task to run before another one, with its own arguments and variables
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency {
+  pub task: String,
+  pub args: Vec<String>,
+  pub variables: HashMap<String, String>,
+}
+
+impl Dependency {
+  pub fn new<S>(task: S) -> Self
+  where
+    S: Into<String>,
+  {
+    Dependency {
+      task: task.into(),
+      args: Vec::new(),
+      variables: HashMap::new(),
+    }
+  }
+
+  /// Arguments then variable overrides, as written on the command line.
+  /// Two dependencies on a same task with different arguments are distinct instances.
+  pub fn arguments(&self) -> Vec<String> {
+    let mut variables: Vec<(&String, &String)> = self.variables.iter().collect();
+    variables.sort();
+
+    let mut arguments = self.args.clone();
+    for (key, value) in variables {
+      arguments.push(format!("--var.{}={}", key, value));
+    }
+    arguments
+  }
+}
+
+/// Parse `name`, `wk:name` or `wk:name arg --var.key=value`
+impl From<&str> for Dependency {
+  fn from(value: &str) -> Self {
+    let value = value.trim();
+    let value = value.strip_prefix("wk:").unwrap_or(value);
+    let (params, vars) = argv::extract_vars(value.split_whitespace());
+
+    let mut params = params.into_iter().map(|s| s.to_string());
+    let mut dependency = Dependency::new(params.next().unwrap_or_default());
+    dependency.args = params.collect();
+    for (key, value) in vars {
+      dependency
+        .variables
+        .insert(key.to_string(), value.to_string());
+    }
+    dependency
+  }
+}
+
+impl From<String> for Dependency {
+  fn from(value: String) -> Self {
+    value.as_str().into()
+  }
+}
+
+impl std::fmt::Display for Dependency {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.task)?;
+    for argument in self.arguments() {
+      write!(f, " {}", argument)?;
+    }
+    Ok(())
+  }
+}
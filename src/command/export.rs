@@ -0,0 +1,61 @@
+use crate::error::Error;
+use std::{collections::HashMap, fmt};
+
+/// Where the value of a variable exported by a task is read from
+#[derive(Debug, Clone, PartialEq)]
+pub enum Export {
+  /// The whole stdout, trimmed
+  Stdout,
+  /// The value of the last `KEY=VALUE` line printed with this key
+  Line(String),
+}
+
+impl From<&str> for Export {
+  fn from(source: &str) -> Self {
+    match source {
+      "stdout" => Export::Stdout,
+      key => Export::Line(key.to_string()),
+    }
+  }
+}
+
+impl std::fmt::Display for Export {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Export::Stdout => write!(f, "stdout"),
+      Export::Line(key) => write!(f, "{}=", key),
+    }
+  }
+}
+
+/// Variables exported by a task from its stdout
+pub fn exported_variables(
+  label: &str,
+  exports: &[(String, Export)],
+  stdout: &str,
+) -> Result<HashMap<String, String>, Error> {
+  let mut variables: HashMap<String, String> = HashMap::new();
+
+  for (name, export) in exports {
+    let value = match export {
+      Export::Stdout => stdout.trim().to_string(),
+      Export::Line(key) => {
+        let prefix = format!("{}=", key);
+        let line = stdout
+          .lines()
+          .rev()
+          .find_map(|line| line.strip_prefix(&prefix));
+        match line {
+          Some(value) => value.trim_end_matches('\r').to_string(),
+          None => {
+            let err = format!("\"{}\" printed no \"{}\" line to export", label, prefix);
+            return Err(Error::Command(err));
+          }
+        }
+      }
+    };
+    variables.insert(name.clone(), value);
+  }
+
+  Ok(variables)
+}
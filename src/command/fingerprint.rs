@@ -0,0 +1,123 @@
+use super::command::Command;
+use crate::{
+  error::Error,
+  utils::{glob, path},
+};
+use fnv::FnvHasher;
+use std::{
+  fs,
+  hash::Hasher,
+  io::{self, Read},
+  path::{Path, PathBuf},
+};
+
+/// Directory of the wk cache, next to the task file
+pub fn cache_dir(source: &Path) -> PathBuf {
+  match source.parent() {
+    Some(dir) => dir.join(".wk"),
+    None => PathBuf::from(".wk"),
+  }
+}
+
+/// Hash of everything a task depends on: its sources, argv, cwd, variables and environments
+#[derive(Debug)]
+pub struct Fingerprint {
+  path: PathBuf,
+  hash: String,
+}
+
+impl Fingerprint {
+  pub fn new(command: &Command) -> Result<Self, Error> {
+    // FNV is stable across runs and Rust versions, unlike the std hasher
+    let mut hasher = FnvHasher::default();
+
+    for arg in command.argv() {
+      write_str(&mut hasher, &arg);
+    }
+
+    let base = command.cwd.clone().unwrap_or_default();
+    write_str(&mut hasher, &base.to_string_lossy());
+
+    let mut variables: Vec<_> = command.variables.iter().collect();
+    variables.sort();
+    for (key, value) in variables {
+      write_str(&mut hasher, key);
+      write_str(&mut hasher, value);
+    }
+
+    let mut environments: Vec<_> = command.environments.iter().collect();
+    environments.sort();
+    for (key, value) in environments {
+      write_str(&mut hasher, key);
+      write_str(&mut hasher, value);
+    }
+
+    for pattern in command.sources.iter() {
+      for file in glob::expand(&base, pattern)? {
+        write_str(&mut hasher, &file.to_string_lossy());
+        write_reader(&mut hasher, fs::File::open(&file)?)?;
+      }
+    }
+
+    Ok(Fingerprint {
+      path: Fingerprint::path(command),
+      hash: format!("{:016x}", hasher.finish()),
+    })
+  }
+
+  /// Where the fingerprint of the command is stored, invocations of a task
+  /// with other variables have their own
+  pub fn path(command: &Command) -> PathBuf {
+    let mut key = command.label();
+    let mut variables: Vec<_> = command.variables.iter().collect();
+    variables.sort();
+    for (name, value) in variables {
+      key.push_str(&format!(" {}={}", name, value));
+    }
+
+    cache_dir(&command.source)
+      .join("fingerprints")
+      .join(path::file_name(&key))
+  }
+
+  /// Nothing changed since the last successful run and every output exists
+  pub fn is_fresh(&self, command: &Command) -> bool {
+    let stored = fs::read_to_string(&self.path).unwrap_or_default();
+    if stored.trim() != self.hash {
+      return false;
+    }
+
+    let base = command.cwd.clone().unwrap_or_default();
+    command
+      .outputs
+      .iter()
+      .all(|pattern| glob::expand(&base, pattern).is_ok_and(|files| !files.is_empty()))
+  }
+
+  pub fn save(&self) -> Result<(), Error> {
+    if let Some(dir) = self.path.parent() {
+      fs::create_dir_all(dir)?;
+    }
+    fs::write(&self.path, &self.hash)?;
+    Ok(())
+  }
+}
+
+/// Hash a string with a separator, so that ["ab", "c"] and ["a", "bc"] differ
+fn write_str(hasher: &mut FnvHasher, s: &str) {
+  hasher.write(s.as_bytes());
+  hasher.write(&[0xff]);
+}
+
+/// Hash everything the reader yields, a chunk at a time
+fn write_reader<R: Read>(hasher: &mut FnvHasher, mut reader: R) -> io::Result<()> {
+  let mut buffer = [0u8; 8192];
+  loop {
+    match reader.read(&mut buffer) {
+      Ok(0) => return Ok(()),
+      Ok(read) => hasher.write(&buffer[..read]),
+      Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+      Err(e) => return Err(e),
+    }
+  }
+}
@@ -1,44 +1,207 @@
-use super::command::Command;
-use crate::error::Error;
+use super::{command::Command, output::Capture, script::ScriptFile, terminal::Terminal};
+use crate::{error::Error, supervisor};
+use futures::channel::oneshot;
 use std::{
   future::Future,
+  io::{self, Read},
   pin::Pin,
-  process::Child,
+  process::{Child, ExitStatus, Stdio},
+  sync::{Arc, Mutex},
   task::{Context, Poll},
+  thread,
+  time::{Duration, Instant},
 };
 
 pub type CommandResult = Result<Option<i32>, Error>;
 
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+enum Exit {
+  Status(io::Result<ExitStatus>),
+  /// A script failed, at the given line
+  Script(ExitStatus, String),
+  TimedOut,
+}
+
 pub struct CommandFuture {
-  process: Option<Result<Child, std::io::Error>>,
+  name: String,
+  timeout: Option<Duration>,
+  process: Option<Result<oneshot::Receiver<Exit>, Error>>,
 }
 
 impl CommandFuture {
-  pub fn new(command: &mut Command) -> Self {
-    let mut cmd = std::process::Command::new(&command.shell);
+  pub fn new(command: &Command) -> Self {
+    Self::spawn(command, None)
+  }
+
+  /// Run the command, its stdout is written to the sink instead of being shown
+  pub fn capture(command: &Command, stdout: Arc<Mutex<String>>) -> Self {
+    Self::spawn(command, Some(stdout))
+  }
+
+  fn spawn(command: &Command, sink: Option<Arc<Mutex<String>>>) -> Self {
+    // Execute and wait for the child process from another thread
+    let timeout = command.timeout;
+    let process = ScriptFile::create(command)
+      .map_err(Error::from)
+      .and_then(|script| {
+        // Asked before the child takes the terminal
+        let terminal = takes_terminal(command);
+        let (mut cmd, pty) = process(command, script.as_ref());
+        if sink.is_some() {
+          cmd.stdout(Stdio::piped());
+        }
+
+        spawn(&mut cmd).map(|mut child| {
+          let (sender, receiver) = oneshot::channel();
+          let pid = child.id();
+          supervisor::register(pid);
+          let log = command.log.clone();
+          let reader = sink.and_then(|sink| {
+            let mut stdout = child.stdout.take()?;
+            Some(thread::spawn(move || {
+              let mut bytes = Vec::new();
+              let _ = stdout.read_to_end(&mut bytes);
+              let content = String::from_utf8_lossy(&bytes).to_string();
+              if let Some(log) = log {
+                content.lines().for_each(|line| log.write("out", line));
+              }
+              *sink.lock().unwrap_or_else(|e| e.into_inner()) = content;
+            }))
+          });
+          let capture = Capture::start(command, &mut child, pty, None);
+
+          thread::spawn(move || {
+            let mut exit = wait(&mut child, timeout);
+            supervisor::unregister(pid);
+            if terminal {
+              let status = match &exit {
+                Exit::Status(Ok(status)) => Some(status),
+                _ => None,
+              };
+              supervisor::reclaim_terminal(status);
+            }
+            capture.finish();
+            if let Some(reader) = reader {
+              let _ = reader.join();
+            }
+
+            // The script file is removed once the process exited
+            if let (Exit::Status(Ok(status)), Some(script)) = (&exit, &script) {
+              if let Some(line) = status.code().and_then(|code| script.failure(code)) {
+                exit = Exit::Script(*status, line);
+              }
+            }
+            drop(script);
+            let _ = sender.send(exit);
+          });
+
+          receiver
+        })
+      });
+
+    Self {
+      name: command.name.clone(),
+      timeout,
+      process: Some(process),
+    }
+  }
+}
+
+/// Process of the command, in its own process group, handed the terminal when it runs in the
+/// foreground. The script of the command, if any, is run from its file.
+/// Comes with the pseudo-terminal it writes to, when it is logged while sharing the terminal.
+pub(crate) fn process(
+  command: &Command,
+  script: Option<&ScriptFile>,
+) -> (std::process::Command, Option<Terminal>) {
+  let argv = match script {
+    Some(script) => script.argv(command),
+    None => command.argv(),
+  };
+  let mut cmd = std::process::Command::new(&argv[0]);
+
+  // Set shell flag and arguments
+  cmd.args(&argv[1..]);
+
+  // Set current directory
+  if let Some(cwd) = &command.cwd {
+    cmd.current_dir(cwd);
+  }
 
-    // Set shell
-    cmd.arg(command.args.remove(0));
+  for env in command.environments.iter() {
+    cmd.env(env.0, env.1);
+  }
 
-    // Set arguments
-    cmd.arg(command.args.join(" "));
+  let pty = command
+    .output_mode()
+    .configure(&mut cmd, command.log.is_some());
 
-    // Set current directory
-    if let Some(cwd) = &command.cwd {
-      cmd.current_dir(cwd);
+  // Tasks get their own process group, so that a timeout stops everything they started.
+  // Signals are forwarded by the supervisor.
+  #[cfg(unix)]
+  {
+    use std::os::unix::process::CommandExt;
+    cmd.process_group(0);
+    if takes_terminal(command) {
+      // Only async-signal-safe calls between fork and exec
+      unsafe {
+        cmd.pre_exec(|| {
+          supervisor::take_terminal();
+          Ok(())
+        });
+      }
     }
+  }
+
+  (cmd, pty)
+}
 
-    for env in command.environments.iter() {
-      cmd.env(env.0, env.1);
+/// A task run alone while wk owns the terminal is handed it,
+/// a background group would be stopped by SIGTTIN when reading from it
+pub(crate) fn takes_terminal(command: &Command) -> bool {
+  command.is_foreground() && supervisor::owns_terminal()
+}
+
+/// Spawn the process, telling when its program cannot be found
+pub(crate) fn spawn(cmd: &mut std::process::Command) -> Result<Child, Error> {
+  cmd.spawn().map_err(|e| {
+    if e.kind() != io::ErrorKind::NotFound {
+      return e.into();
     }
 
-    // Execute and store child process
-    let child = cmd.spawn();
+    if let Some(cwd) = cmd.get_current_dir().filter(|cwd| !cwd.is_dir()) {
+      let err = format!("Directory \"{}\" not found", cwd.to_string_lossy());
+      return Error::Command(err);
+    }
 
-    Self {
-      process: Some(child),
+    let program = cmd.get_program().to_string_lossy();
+    let err = if program.contains(std::path::MAIN_SEPARATOR) || program.contains('/') {
+      format!("Program \"{}\" not found", program)
+    } else {
+      format!("Program \"{}\" not found in PATH", program)
+    };
+    Error::Command(err)
+  })
+}
+
+fn wait(child: &mut Child, timeout: Option<Duration>) -> Exit {
+  let deadline = match timeout {
+    Some(timeout) => Instant::now() + timeout,
+    None => return Exit::Status(child.wait()),
+  };
+
+  loop {
+    match child.try_wait() {
+      Ok(Some(status)) => return Exit::Status(Ok(status)),
+      Ok(None) if Instant::now() >= deadline => break,
+      Ok(None) => thread::sleep(POLL_INTERVAL),
+      Err(e) => return Exit::Status(Err(e)),
     }
   }
+
+  supervisor::terminate(child);
+  Exit::TimedOut
 }
 
 impl Future for CommandFuture {
@@ -46,21 +209,44 @@ impl Future for CommandFuture {
 
   fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
     let runner = self.get_mut();
-    let wake = || {
-      let w = cx.waker().clone();
-      w.wake();
-    };
 
     match runner.process.take() {
-      Some(Ok(mut child)) => match child.wait() {
-        Ok(e) => Poll::Ready(Ok(e.code())),
-        Err(e) => Poll::Ready(Err(e.into())),
+      Some(Ok(mut receiver)) => match Pin::new(&mut receiver).poll(cx) {
+        Poll::Ready(Ok(Exit::Status(Ok(status)))) => Poll::Ready(Ok(exit_code(status))),
+        Poll::Ready(Ok(Exit::Status(Err(e)))) => Poll::Ready(Err(e.into())),
+        Poll::Ready(Ok(Exit::Script(status, line))) => Poll::Ready(Err(Error::Script(
+          runner.name.clone(),
+          exit_code(status).unwrap_or(1),
+          line,
+        ))),
+        Poll::Ready(Ok(Exit::TimedOut)) => Poll::Ready(Err(Error::Timeout(
+          runner.name.clone(),
+          runner.timeout.unwrap_or_default(),
+        ))),
+        Poll::Ready(Err(_)) => Poll::Ready(Err(Error::Command(
+          "Lost track of the child process".to_string(),
+        ))),
+        Poll::Pending => {
+          runner.process = Some(Ok(receiver));
+          Poll::Pending
+        }
       },
-      Some(Err(e)) => Poll::Ready(Err(e.into())),
-      None => {
-        wake();
-        Poll::Pending
-      }
+      Some(Err(e)) => Poll::Ready(Err(e)),
+      None => Poll::Pending,
     }
   }
 }
+
+/// Exit code of the process, signal terminations are reported as 128+N like shells do
+#[cfg(unix)]
+pub(crate) fn exit_code(status: ExitStatus) -> Option<i32> {
+  use std::os::unix::process::ExitStatusExt;
+  status
+    .code()
+    .or_else(|| status.signal().map(|signal| 128 + signal))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn exit_code(status: ExitStatus) -> Option<i32> {
+  status.code()
+}
@@ -0,0 +1,71 @@
+use super::builder::replace_variables;
+use serde::Serialize;
+use std::{collections::HashMap, fmt};
+
+/// Steps wrapping a task, each one a shell command or a `wk:` task
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Hooks {
+  /// Run before the task, the task does not run when one fails
+  pub before: Vec<String>,
+  /// Run once the task succeeded
+  pub after: Vec<String>,
+  /// Run when the task or one of its steps failed
+  pub on_failure: Vec<String>,
+  /// Run whatever the outcome, even when interrupted
+  pub finally: Vec<String>,
+}
+
+impl Hooks {
+  pub fn is_empty(&self) -> bool {
+    self.before.is_empty()
+      && self.after.is_empty()
+      && self.on_failure.is_empty()
+      && self.finally.is_empty()
+  }
+
+  /// Wrap the hooks of a task with the ones of its file:
+  /// the file steps run first before the task and last after it
+  pub fn wrap(&mut self, outer: &Hooks) {
+    let mut before = outer.before.clone();
+    before.append(&mut self.before);
+    self.before = before;
+
+    self.after.extend(outer.after.iter().cloned());
+    self.on_failure.extend(outer.on_failure.iter().cloned());
+    self.finally.extend(outer.finally.iter().cloned());
+  }
+
+  pub fn replace_variables(&self, variables: &HashMap<String, String>) -> Hooks {
+    let replace = |steps: &Vec<String>| -> Vec<String> {
+      steps
+        .iter()
+        .map(|step| replace_variables(step, variables))
+        .collect()
+    };
+
+    Hooks {
+      before: replace(&self.before),
+      after: replace(&self.after),
+      on_failure: replace(&self.on_failure),
+      finally: replace(&self.finally),
+    }
+  }
+}
+
+impl std::fmt::Display for Hooks {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let hooks = [
+      ("Before", &self.before),
+      ("After", &self.after),
+      ("On failure", &self.on_failure),
+      ("Finally", &self.finally),
+    ];
+
+    for (kind, steps) in hooks.iter() {
+      if !steps.is_empty() {
+        writeln!(f, "{}: {}", kind, steps.join(", "))?;
+      }
+    }
+    Ok(())
+  }
+}
@@ -0,0 +1,47 @@
+use super::{command::Command, origin::Origin};
+use crate::utils::argv::quote;
+use std::{fmt, path::Path};
+
+/// Everything a task resolves to before being executed
+#[derive(Debug)]
+pub struct Inspection<'a> {
+  pub command: Command,
+  pub description: Option<&'a str>,
+  pub source: &'a Path,
+  pub order: Vec<String>,
+  pub variables: Vec<(String, String, Origin)>,
+  pub environments: Vec<(String, String, Origin)>,
+}
+
+impl<'a> std::fmt::Display for Inspection<'a> {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "Task: {}", self.command.name)?;
+
+    if let Some(description) = self.description {
+      writeln!(f, "Description: {}", description)?;
+    }
+
+    writeln!(f, "Source: {}", self.source.to_string_lossy())?;
+    writeln!(f, "Order: {}", self.order.join(" -> "))?;
+    writeln!(f, "Shell: {}", self.command.shell.to_string_lossy())?;
+
+    let argv: Vec<String> = self.command.argv().iter().map(|arg| quote(arg)).collect();
+    writeln!(f, "Argv: {}", argv.join(" "))?;
+
+    if let Some(cwd) = &self.command.cwd {
+      writeln!(f, "Cwd: {}", cwd.to_string_lossy())?;
+    }
+
+    writeln!(f, "Variables:")?;
+    for (key, value, origin) in self.variables.iter() {
+      writeln!(f, "  {}={} ({})", key, value, origin)?;
+    }
+
+    writeln!(f, "Environments:")?;
+    for (key, value, origin) in self.environments.iter() {
+      writeln!(f, "  {}={} ({})", key, value, origin)?;
+    }
+
+    Ok(())
+  }
+}
@@ -1,7 +1,34 @@
 mod builder;
+#[allow(clippy::module_inception)]
 mod command;
+mod condition;
+mod dependency;
+mod export;
+mod fingerprint;
 mod future;
+mod hooks;
+mod inspect;
+mod origin;
+mod output;
+mod plan;
+mod retry;
+mod script;
+mod service;
+mod shell;
+mod terminal;
 
 pub use builder::*;
 pub use command::*;
+pub use condition::*;
+pub use dependency::*;
+pub use export::*;
+pub use fingerprint::*;
 pub use future::*;
+pub use hooks::*;
+pub use inspect::*;
+pub use origin::*;
+pub use output::*;
+pub use plan::*;
+pub use retry::*;
+pub use service::*;
+pub use shell::*;
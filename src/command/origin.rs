@@ -0,0 +1,31 @@
+use std::{collections::HashMap, fmt, path::PathBuf};
+
+/// Where a variable or an environment value has been defined
+#[derive(Debug, Clone, PartialEq)]
+pub enum Origin {
+  Builtin,
+  File(PathBuf),
+  Extends(PathBuf),
+  Task(String),
+  Cli,
+}
+
+impl std::fmt::Display for Origin {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Origin::Builtin => write!(f, "built-in"),
+      Origin::File(path) => write!(f, "file {}", path.to_string_lossy()),
+      Origin::Extends(path) => write!(f, "extends {}", path.to_string_lossy()),
+      Origin::Task(name) => write!(f, "task {}", name),
+      Origin::Cli => write!(f, "cli"),
+    }
+  }
+}
+
+/// Variables available to every task, prefixed with `wk.` not to clash with the ones of the files
+pub fn builtin_variables() -> HashMap<String, String> {
+  let mut variables = HashMap::new();
+  variables.insert("wk.os".to_string(), std::env::consts::OS.to_string());
+  variables.insert("wk.arch".to_string(), std::env::consts::ARCH.to_string());
+  variables
+}
@@ -0,0 +1,315 @@
+use super::{command::Command, terminal::Terminal};
+use crate::{error::Error, logs::TaskLog};
+use serde::{Deserialize, Serialize};
+use std::{
+  env, fmt,
+  io::{self, BufRead, BufReader, IsTerminal, Read, Write},
+  process::{self, Child, Stdio},
+  str::FromStr,
+  sync::{Arc, Mutex},
+  thread::{self, JoinHandle},
+};
+
+/// Colors given to the labels of the tasks, red is kept for errors
+const COLORS: [u8; 6] = [36, 35, 33, 32, 34, 96];
+
+/// How the stdout and stderr of a task are shown
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Output {
+  /// Streams are shared with wk
+  Inherit,
+  /// Each line is prefixed with the label of the task
+  Prefixed,
+  /// Lines are printed in one block once the task finished
+  Grouped,
+  /// Streams are discarded
+  Silent,
+}
+
+impl Output {
+  /// Set the streams of the process, piped when they are read line by line.
+  /// A logged task sharing the terminal of wk writes to a pseudo-terminal instead, returned
+  /// to be read, so that it still finds a terminal.
+  pub(crate) fn configure(self, cmd: &mut process::Command, logged: bool) -> Option<Terminal> {
+    let stdio = || match self {
+      _ if logged => Stdio::piped(),
+      Output::Prefixed | Output::Grouped => Stdio::piped(),
+      Output::Silent => Stdio::null(),
+      Output::Inherit => Stdio::inherit(),
+    };
+
+    let shared = [Stream::Stdout, Stream::Stderr].map(Stream::is_terminal);
+    let terminal = match self {
+      Output::Inherit if logged && shared.contains(&true) => Terminal::open().ok(),
+      _ => None,
+    };
+    let stream = |shared: bool| match &terminal {
+      Some(terminal) if shared => terminal.stdio().unwrap_or_else(|_| stdio()),
+      _ => stdio(),
+    };
+    cmd.stdout(stream(shared[0]));
+    cmd.stderr(stream(shared[1]));
+    terminal
+  }
+}
+
+impl FromStr for Output {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "inherit" => Ok(Output::Inherit),
+      "prefixed" => Ok(Output::Prefixed),
+      "grouped" => Ok(Output::Grouped),
+      "silent" => Ok(Output::Silent),
+      _ => Err(Error::Command(format!(
+        "Unknown output \"{}\", expected inherit, prefixed, grouped or silent",
+        s
+      ))),
+    }
+  }
+}
+
+impl std::fmt::Display for Output {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      Output::Inherit => "inherit",
+      Output::Prefixed => "prefixed",
+      Output::Grouped => "grouped",
+      Output::Silent => "silent",
+    };
+    write!(f, "{}", name)
+  }
+}
+
+/// Label of a task, colored the same way for the whole run
+pub fn prefix(label: &str, color: bool) -> String {
+  if !color {
+    return format!("{} | ", label);
+  }
+
+  let hash = label.bytes().fold(0usize, |hash, b| {
+    hash.wrapping_mul(31).wrapping_add(b as usize)
+  });
+  format!("\x1b[{}m{}\x1b[0m | ", COLORS[hash % COLORS.len()], label)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Stream {
+  Stdout,
+  Stderr,
+}
+
+impl Stream {
+  fn name(self) -> &'static str {
+    match self {
+      Stream::Stdout => "out",
+      Stream::Stderr => "err",
+    }
+  }
+
+  fn is_terminal(self) -> bool {
+    match self {
+      Stream::Stdout => io::stdout().is_terminal(),
+      Stream::Stderr => io::stderr().is_terminal(),
+    }
+  }
+
+  fn is_colored(self) -> bool {
+    env::var_os("NO_COLOR").is_none() && self.is_terminal()
+  }
+
+  fn write_line(self, line: &str) {
+    // A closed stream does not stop the task, its output is still drained
+    let _ = match self {
+      Stream::Stdout => writeln!(io::stdout().lock(), "{}", line),
+      Stream::Stderr => writeln!(io::stderr().lock(), "{}", line),
+    };
+  }
+
+  /// Write bytes as they are read, without waiting for the end of the line
+  fn write(self, bytes: &[u8]) {
+    let _ = match self {
+      Stream::Stdout => {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(bytes).and_then(|_| stdout.flush())
+      }
+      Stream::Stderr => io::stderr().lock().write_all(bytes),
+    };
+  }
+}
+
+/// Called with each line of the stdout of a process
+pub(crate) type Observer = Box<dyn Fn(&str) + Send>;
+
+type Block = Arc<Mutex<Vec<(Stream, String)>>>;
+
+/// Piped streams of a process, read line by line until it exits
+pub(crate) struct Capture {
+  label: String,
+  readers: Vec<JoinHandle<()>>,
+  block: Option<Block>,
+}
+
+impl Capture {
+  /// Read the piped streams of the process of the command, and the pseudo-terminal it writes to
+  pub(crate) fn start(
+    command: &Command,
+    child: &mut Child,
+    terminal: Option<Terminal>,
+    observer: Option<Observer>,
+  ) -> Self {
+    let output = command.output_mode();
+    let block: Option<Block> = match output {
+      Output::Grouped => Some(Arc::new(Mutex::new(Vec::new()))),
+      _ => None,
+    };
+    let lines = Lines {
+      output,
+      label: command.label(),
+      block: block.clone(),
+      log: command.log.clone(),
+    };
+
+    let mut readers = Vec::new();
+    // The stdout of the process is either piped or written to the terminal
+    let mut observer = observer;
+    if let Some(stdout) = child.stdout.take() {
+      let lines = lines.clone();
+      let observer = observer.take();
+      readers.push(thread::spawn(move || {
+        lines.read(stdout, Stream::Stdout, observer)
+      }));
+    }
+    if let Some(terminal) = terminal {
+      let lines = lines.clone();
+      let stream = match Stream::Stdout.is_terminal() {
+        true => Stream::Stdout,
+        false => Stream::Stderr,
+      };
+      // Dropping the other end, the terminal is closed once the process exited
+      let reader = terminal.reader;
+      readers.push(thread::spawn(move || lines.copy(reader, stream, observer)));
+    }
+    if let Some(stderr) = child.stderr.take() {
+      let lines = lines.clone();
+      readers.push(thread::spawn(move || {
+        lines.read(stderr, Stream::Stderr, None)
+      }));
+    }
+
+    Capture {
+      label: lines.label,
+      readers,
+      block,
+    }
+  }
+
+  /// Wait for the streams to be closed, a grouped output is printed at once
+  pub(crate) fn finish(self) {
+    for reader in self.readers {
+      let _ = reader.join();
+    }
+
+    let block = match self.block {
+      Some(block) => block,
+      None => return,
+    };
+    let lines = block.lock().unwrap_or_else(|e| e.into_inner());
+    if lines.is_empty() {
+      return;
+    }
+
+    // Both streams are held so that the block is not interleaved with other tasks
+    let _stdout = io::stdout().lock();
+    let _stderr = io::stderr().lock();
+    let header = prefix(&self.label, Stream::Stdout.is_colored());
+    Stream::Stdout.write_line(&format!("{}:", header.trim_end_matches(" | ")));
+    for (stream, line) in lines.iter() {
+      stream.write_line(line);
+    }
+  }
+}
+
+/// Where the lines read from the streams of a process go
+#[derive(Clone)]
+struct Lines {
+  output: Output,
+  label: String,
+  block: Option<Block>,
+  log: Option<TaskLog>,
+}
+
+impl Lines {
+  fn read<R: Read>(&self, source: R, stream: Stream, observer: Option<Observer>) {
+    let prefix = prefix(&self.label, stream.is_colored());
+    let mut reader = BufReader::new(source);
+    let mut buffer = Vec::new();
+
+    loop {
+      buffer.clear();
+      match reader.read_until(b'\n', &mut buffer) {
+        Ok(0) | Err(_) => break,
+        Ok(_) => {}
+      }
+
+      let line = String::from_utf8_lossy(&buffer);
+      let line = line.trim_end_matches(&['\n', '\r'][..]);
+      self.record(line, stream, &observer);
+
+      match (self.output, &self.block) {
+        (Output::Prefixed, _) => stream.write_line(&format!("{}{}", prefix, line)),
+        (Output::Grouped, Some(block)) => block
+          .lock()
+          .unwrap_or_else(|e| e.into_inner())
+          .push((stream, line.to_string())),
+        (Output::Silent, _) => {}
+        _ => stream.write_line(line),
+      }
+    }
+  }
+  /// Copy the output written to a pseudo-terminal as it comes, each line of it is logged
+  fn copy<R: Read>(&self, mut source: R, stream: Stream, observer: Option<Observer>) {
+    let mut buffer = [0; 4096];
+    let mut line: Vec<u8> = Vec::new();
+    let mut returned = false;
+
+    // Reading fails once nothing writes to the terminal anymore
+    while let Ok(read @ 1..) = source.read(&mut buffer) {
+      stream.write(&buffer[..read]);
+      for byte in &buffer[..read] {
+        match byte {
+          b'\n' => {
+            self.record(&String::from_utf8_lossy(&line), stream, &observer);
+            line.clear();
+          }
+          b'\r' => {
+            returned = true;
+            continue;
+          }
+          // A carriage return not ending the line starts it over, like a progress bar does
+          byte if returned => {
+            line.clear();
+            line.push(*byte);
+          }
+          byte => line.push(*byte),
+        }
+        returned = false;
+      }
+    }
+
+    if !line.is_empty() {
+      self.record(&String::from_utf8_lossy(&line), stream, &observer);
+    }
+  }
+
+  fn record(&self, line: &str, stream: Stream, observer: &Option<Observer>) {
+    if let Some(observer) = observer {
+      observer(line);
+    }
+    if let Some(log) = &self.log {
+      log.write(stream.name(), line);
+    }
+  }
+}
@@ -0,0 +1,65 @@
+use super::{command::Command, hooks::Hooks, output::Output};
+use serde::Serialize;
+use std::{collections::BTreeMap, path::PathBuf};
+
+/// What would be spawned for a task, in a serializable form
+#[derive(Debug, Serialize)]
+pub struct PlanStep {
+  pub task: String,
+  pub arguments: Vec<String>,
+  pub program: String,
+  pub args: Vec<String>,
+  pub cwd: Option<PathBuf>,
+  pub group: Option<String>,
+  pub series: Option<String>,
+  pub timeout: Option<f64>,
+  pub attempts: u32,
+  pub dependencies: Vec<String>,
+  pub variables: BTreeMap<String, String>,
+  pub environments: BTreeMap<String, String>,
+  pub when: Option<String>,
+  pub service: bool,
+  pub output: Output,
+  /// Exported variables and where they are read from
+  pub exports: BTreeMap<String, String>,
+  pub hooks: Hooks,
+}
+
+impl From<&Command> for PlanStep {
+  fn from(value: &Command) -> Self {
+    let mut argv = value.argv();
+    let program = argv.remove(0);
+
+    PlanStep {
+      task: value.name.clone(),
+      arguments: value.arguments.clone(),
+      program,
+      args: argv,
+      cwd: value.cwd.clone(),
+      group: value.group.as_ref().and(value.group_label()),
+      series: value.series.as_ref().and(value.group_label()),
+      timeout: value.timeout.map(|timeout| timeout.as_secs_f64()),
+      attempts: value.retry.as_ref().map_or(1, |retry| retry.attempts),
+      dependencies: value.dependencies.iter().map(|d| d.to_string()).collect(),
+      variables: value
+        .variables
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect(),
+      environments: value
+        .environments
+        .iter()
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect(),
+      when: value.when.as_ref().map(|when| when.to_string()),
+      service: value.service,
+      output: value.output_mode(),
+      exports: value
+        .exports
+        .iter()
+        .map(|(name, export)| (name.clone(), export.to_string()))
+        .collect(),
+      hooks: value.hooks.clone(),
+    }
+  }
+}
@@ -0,0 +1,48 @@
+use super::future::CommandResult;
+use crate::error::Error;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Backoff {
+  Fixed,
+  Exponential,
+}
+
+/// How many times a failing task is spawned again, and when
+#[derive(Debug, Clone)]
+pub struct Retry {
+  pub attempts: u32,
+  pub delay: Duration,
+  pub backoff: Backoff,
+  pub on_exit_codes: Vec<i32>,
+}
+
+impl Retry {
+  /// Delay before the attempt following `attempt`, attempts start at 1
+  pub fn delay(&self, attempt: u32) -> Duration {
+    match self.backoff {
+      Backoff::Fixed => self.delay,
+      Backoff::Exponential => self
+        .delay
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1))),
+    }
+  }
+
+  /// Whether the result of `attempt` is worth another try
+  pub fn should_retry(&self, attempt: u32, result: &CommandResult) -> bool {
+    if attempt >= self.attempts {
+      return false;
+    }
+
+    let code = match result {
+      Ok(Some(0)) => return false,
+      Ok(Some(code)) => *code,
+      Ok(None) => 1,
+      Err(Error::Script(_, code, _)) => *code,
+      Err(error @ Error::Timeout(_, _)) => error.exit_code(),
+      Err(_) => return false,
+    };
+
+    self.on_exit_codes.is_empty() || self.on_exit_codes.contains(&code)
+  }
+}
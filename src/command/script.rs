@@ -0,0 +1,162 @@
+use super::command::Command;
+use crate::utils::path;
+use std::{
+  env, fs,
+  io::{self, Write},
+  path::{Path, PathBuf},
+  process,
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Runs the script from bash, recording the status and line of the last failing command next to it
+const BASH_PRELUDE: &str = "set -E; trap 'echo \"$? $LINENO\" > \"$0.line\"' ERR; . \"$0\"";
+
+/// The script of a task written to a temporary file, removed once dropped
+#[derive(Debug)]
+pub struct ScriptFile {
+  path: PathBuf,
+  source: String,
+}
+
+impl ScriptFile {
+  /// Write the script of the command, None when the command has no script
+  pub fn create(command: &Command) -> io::Result<Option<Self>> {
+    let source = match &command.script {
+      Some(script) => script.clone(),
+      None => return Ok(None),
+    };
+
+    let dir = directory()?;
+    loop {
+      let name = format!(
+        "{}-{}-{}{}",
+        process::id(),
+        COUNT.fetch_add(1, Ordering::SeqCst),
+        path::file_name(&command.name),
+        extension(&command.interpreter)
+      );
+      let path = dir.join(name);
+
+      // Never write through a file left by a previous process with the same pid
+      let mut file = match open(&path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+        Err(e) => return Err(e),
+      };
+      file.write_all(source.as_bytes())?;
+      return Ok(Some(ScriptFile { path, source }));
+    }
+  }
+
+  pub fn path(&self) -> &Path {
+    &self.path
+  }
+
+  /// Argv running the script file with the interpreter of the command
+  pub fn argv(&self, command: &Command) -> Vec<String> {
+    let mut argv = command.interpreter.clone();
+    if is_bash(&command.interpreter) {
+      argv.push("-c".to_string());
+      argv.push(BASH_PRELUDE.to_string());
+    }
+    argv.push(self.path.to_string_lossy().to_string());
+    argv
+  }
+
+  /// Line the script failed at, when the interpreter reported a command failing with the
+  /// code the script exited with. A failure handled before another exit is not the cause.
+  pub fn failure(&self, code: i32) -> Option<String> {
+    let recorded = fs::read_to_string(self.line_path()).ok()?;
+    let (status, line) = recorded.trim().split_once(' ')?;
+    if status.parse::<i32>().ok()? != code {
+      return None;
+    }
+
+    let line: usize = line.parse().ok()?;
+    let text = self.source.lines().nth(line.checked_sub(1)?)?;
+    Some(format!("line {}: {}", line, text.trim()))
+  }
+
+  fn line_path(&self) -> PathBuf {
+    let mut name = self.path.as_os_str().to_owned();
+    name.push(".line");
+    PathBuf::from(name)
+  }
+}
+
+impl Drop for ScriptFile {
+  fn drop(&mut self) {
+    let _ = fs::remove_file(&self.path);
+    let _ = fs::remove_file(self.line_path());
+  }
+}
+
+/// Directory of the script files, only readable by the current user
+#[cfg(unix)]
+fn directory() -> io::Result<PathBuf> {
+  use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+  let uid = unsafe { libc::getuid() };
+  let dir = env::temp_dir().join(format!("wk-{}", uid));
+  match fs::DirBuilder::new().mode(0o700).create(&dir) {
+    Err(e) if e.kind() != io::ErrorKind::AlreadyExists => return Err(e),
+    _ => {}
+  }
+
+  // The directory may have been created by someone else first
+  let metadata = fs::symlink_metadata(&dir)?;
+  if !metadata.is_dir() || metadata.uid() != uid || metadata.permissions().mode() & 0o077 != 0 {
+    let err = format!("{} is not a private directory", dir.to_string_lossy());
+    return Err(io::Error::new(io::ErrorKind::PermissionDenied, err));
+  }
+  Ok(dir)
+}
+
+/// The temporary directory of Windows is already private to the user
+#[cfg(not(unix))]
+fn directory() -> io::Result<PathBuf> {
+  let dir = env::temp_dir().join("wk");
+  fs::create_dir_all(&dir)?;
+  Ok(dir)
+}
+
+#[cfg(unix)]
+fn open(path: &Path) -> io::Result<fs::File> {
+  use std::os::unix::fs::OpenOptionsExt;
+  fs::OpenOptions::new()
+    .write(true)
+    .create_new(true)
+    .mode(0o600)
+    .open(path)
+}
+
+#[cfg(not(unix))]
+fn open(path: &Path) -> io::Result<fs::File> {
+  fs::OpenOptions::new()
+    .write(true)
+    .create_new(true)
+    .open(path)
+}
+
+fn program(interpreter: &[String]) -> String {
+  interpreter
+    .first()
+    .and_then(|program| Path::new(program).file_stem())
+    .map(|stem| stem.to_string_lossy().to_lowercase())
+    .unwrap_or_default()
+}
+
+fn is_bash(interpreter: &[String]) -> bool {
+  program(interpreter) == "bash"
+}
+
+/// Some interpreters only run files with the right extension
+fn extension(interpreter: &[String]) -> &'static str {
+  match program(interpreter).as_str() {
+    "cmd" => ".cmd",
+    "pwsh" | "powershell" => ".ps1",
+    _ => "",
+  }
+}
@@ -0,0 +1,196 @@
+use super::{
+  command::Command,
+  condition::Condition,
+  future::{exit_code, process, spawn},
+  output::{Capture, Observer},
+  script::ScriptFile,
+};
+use crate::{error::Error, supervisor, utils::timer};
+use regex::Regex;
+use std::{
+  fmt,
+  net::{SocketAddr, TcpStream},
+  process::Stdio,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc, Arc,
+  },
+  thread,
+  time::{Duration, Instant},
+};
+
+/// Time given to a service to get ready when its task has no timeout
+pub const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+const PROBE_INTERVAL: Duration = Duration::from_millis(100);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// What tells that a service can be used by its dependents
+#[derive(Debug, Clone)]
+pub enum Probe {
+  Port(u16),
+  Stdout(Regex),
+  File(String),
+  Command(String),
+}
+
+impl std::fmt::Display for Probe {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Probe::Port(port) => write!(f, "port {} open", port),
+      Probe::Stdout(regex) => write!(f, "stdout matches {}", regex),
+      Probe::File(path) => write!(f, "file {} exists", path),
+      Probe::Command(script) => write!(f, "command {} succeeds", script),
+    }
+  }
+}
+
+/// A task running in the background of the run, stopped when dropped
+pub struct Service {
+  command: Command,
+  exit: mpsc::Receiver<Option<i32>>,
+  stopping: Arc<AtomicBool>,
+  matched: Arc<AtomicBool>,
+  running: bool,
+}
+
+impl Service {
+  pub fn start(command: Command) -> Result<Self, Error> {
+    let script = ScriptFile::create(&command)?;
+    let (mut cmd, pty) = process(&command, script.as_ref());
+    if let Some(Probe::Stdout(_)) = &command.ready {
+      cmd.stdout(Stdio::piped());
+    }
+
+    let mut child = spawn(&mut cmd)?;
+    let pid = child.id();
+    supervisor::register(pid);
+
+    // Forward the output of the service while looking for the expected line
+    let matched = Arc::new(AtomicBool::new(false));
+    let observer: Option<Observer> = match &command.ready {
+      Some(Probe::Stdout(regex)) => {
+        let regex = regex.clone();
+        let matched = matched.clone();
+        Some(Box::new(move |line: &str| {
+          if !matched.load(Ordering::SeqCst) && regex.is_match(line) {
+            matched.store(true, Ordering::SeqCst);
+          }
+        }))
+      }
+      _ => None,
+    };
+    let capture = Capture::start(&command, &mut child, pty, observer);
+
+    let (sender, exit) = mpsc::channel();
+    let stopping = Arc::new(AtomicBool::new(false));
+    let stop = stopping.clone();
+    thread::spawn(move || {
+      let code = loop {
+        match child.try_wait() {
+          Ok(Some(status)) => break exit_code(status),
+          Ok(None) if stop.load(Ordering::SeqCst) => {
+            supervisor::terminate(&mut child);
+            break None;
+          }
+          Ok(None) => thread::sleep(POLL_INTERVAL),
+          Err(_) => break None,
+        }
+      };
+      supervisor::unregister(pid);
+      capture.finish();
+      drop(script);
+      let _ = sender.send(code);
+    });
+
+    Ok(Service {
+      command,
+      exit,
+      stopping,
+      matched,
+      running: true,
+    })
+  }
+
+  pub fn label(&self) -> String {
+    self.command.label()
+  }
+
+  /// Wait for the probe to succeed, within the timeout of the task
+  pub async fn ready(&mut self) -> Result<(), Error> {
+    let timeout = self.command.timeout.unwrap_or(DEFAULT_READY_TIMEOUT);
+    let deadline = Instant::now() + timeout;
+
+    loop {
+      if let Ok(code) = self.exit.try_recv() {
+        self.running = false;
+        let err = format!(
+          "\"{}\" exited with code {} before being ready",
+          self.command.name,
+          code.unwrap_or(1)
+        );
+        return Err(Error::Command(err));
+      }
+
+      if self.is_ready() {
+        return Ok(());
+      }
+
+      if let Some(signal) = supervisor::interrupted() {
+        return Err(Error::Interrupted(signal));
+      }
+
+      if Instant::now() >= deadline {
+        return Err(Error::Timeout(self.command.name.clone(), timeout));
+      }
+
+      timer::delay(PROBE_INTERVAL).await;
+    }
+  }
+
+  fn is_ready(&self) -> bool {
+    match &self.command.ready {
+      None => true,
+      Some(Probe::Port(port)) => {
+        let address = SocketAddr::from(([127, 0, 0, 1], *port));
+        TcpStream::connect_timeout(&address, PROBE_INTERVAL).is_ok()
+      }
+      Some(Probe::Stdout(_)) => self.matched.load(Ordering::SeqCst),
+      Some(Probe::File(path)) => Condition::Exists(path.clone()).evaluate(&self.command),
+      Some(Probe::Command(script)) => Condition::Shell(script.clone()).evaluate(&self.command),
+    }
+  }
+
+  /// Exit code of the service once it exited on its own, None while it runs
+  pub fn exited(&mut self) -> Option<Option<i32>> {
+    if !self.running {
+      return None;
+    }
+
+    let code = self.exit.try_recv().ok()?;
+    self.running = false;
+    Some(code)
+  }
+
+  /// Terminate the service, politely first, and wait for it to exit
+  pub fn stop(&mut self) {
+    if !self.running {
+      return;
+    }
+    self.running = false;
+
+    if self.exit.try_recv().is_ok() {
+      return;
+    }
+
+    verbose!("Stop \"{}\"", self.command.label());
+    self.stopping.store(true, Ordering::SeqCst);
+    let _ = self.exit.recv();
+  }
+}
+
+impl Drop for Service {
+  fn drop(&mut self) {
+    self.stop();
+  }
+}
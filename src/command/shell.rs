@@ -0,0 +1,99 @@
+use std::{env, fmt, path::Path, path::PathBuf};
+
+/// Program running the commands, with the arguments put before the command
+#[derive(Debug, Clone, PartialEq)]
+pub struct Shell {
+  pub program: PathBuf,
+  pub args: Vec<String>,
+}
+
+impl Shell {
+  pub fn new<P, I, S>(program: P, args: I) -> Self
+  where
+    P: Into<PathBuf>,
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    Shell {
+      program: program.into(),
+      args: args.into_iter().map(|s| s.into()).collect(),
+    }
+  }
+
+  /// Shell with the arguments its program needs to run a command:
+  /// bash, sh, zsh and fish take `-c`, pwsh `-NoProfile -Command` and cmd `/c`
+  pub fn preset<P>(program: P) -> Self
+  where
+    P: Into<PathBuf>,
+  {
+    let program = program.into();
+    let args: &[&str] = match name(&program).as_str() {
+      "pwsh" | "powershell" => &["-NoProfile", "-Command"],
+      "cmd" => &["/c"],
+      "wsl" => &["-e", "sh", "-c"],
+      _ => &["-c"],
+    };
+    Shell::new(program, args.iter().copied())
+  }
+
+  /// Shell of the user, `$SHELL` or the default shell when not set
+  pub fn user() -> Self {
+    match env::var("SHELL") {
+      Ok(shell) if !shell.is_empty() => Shell::preset(shell),
+      _ => Shell::default(),
+    }
+  }
+
+  /// Program and arguments running a script file with this shell
+  pub fn interpreter(&self) -> Vec<String> {
+    let args: &[&str] = match name(&self.program).as_str() {
+      "pwsh" | "powershell" => &["-NoProfile", "-File"],
+      "cmd" => &["/c"],
+      "wsl" => &["-e", "sh"],
+      _ => &[],
+    };
+
+    let mut interpreter = vec![self.program.to_string_lossy().to_string()];
+    interpreter.extend(args.iter().map(|arg| arg.to_string()));
+    interpreter
+  }
+}
+
+/// cmd.exe on Windows, else bash
+impl Default for Shell {
+  fn default() -> Self {
+    if cfg!(windows) {
+      return Shell::preset("cmd.exe");
+    }
+    Shell::preset("bash")
+  }
+}
+
+impl From<PathBuf> for Shell {
+  fn from(program: PathBuf) -> Self {
+    Shell::preset(program)
+  }
+}
+
+impl From<&str> for Shell {
+  fn from(program: &str) -> Self {
+    Shell::preset(program)
+  }
+}
+
+impl std::fmt::Display for Shell {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.program.to_string_lossy())?;
+    for arg in self.args.iter() {
+      write!(f, " {}", arg)?;
+    }
+    Ok(())
+  }
+}
+
+fn name(program: &Path) -> String {
+  program
+    .file_stem()
+    .map(|stem| stem.to_string_lossy().to_lowercase())
+    .unwrap_or_default()
+}
@@ -0,0 +1,58 @@
+use std::{fs::File, io, process::Stdio};
+
+/// Pseudo-terminal given to a logged task in place of the terminal of wk, so that the task
+/// still writes to a terminal. What it writes is read from the other end to be shown and logged.
+pub(crate) struct Terminal {
+  /// Read by wk, ends once every process writing to the terminal exited
+  pub(crate) reader: File,
+  writer: File,
+}
+
+impl Terminal {
+  /// Open a pseudo-terminal the size of the terminal of wk
+  #[cfg(unix)]
+  pub(crate) fn open() -> io::Result<Self> {
+    use std::os::unix::io::FromRawFd;
+
+    let mut reader: libc::c_int = -1;
+    let mut writer: libc::c_int = -1;
+    unsafe {
+      let mut size: libc::winsize = std::mem::zeroed();
+      let size = match libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) {
+        0 => &mut size as *mut libc::winsize,
+        _ => std::ptr::null_mut(),
+      };
+      let opened = libc::openpty(
+        &mut reader,
+        &mut writer,
+        std::ptr::null_mut(),
+        std::ptr::null_mut(),
+        size,
+      );
+      if opened != 0 {
+        return Err(io::Error::last_os_error());
+      }
+
+      // Other tasks spawned meanwhile must not keep the terminal open
+      libc::fcntl(reader, libc::F_SETFD, libc::FD_CLOEXEC);
+      libc::fcntl(writer, libc::F_SETFD, libc::FD_CLOEXEC);
+      Ok(Terminal {
+        reader: File::from_raw_fd(reader),
+        writer: File::from_raw_fd(writer),
+      })
+    }
+  }
+
+  #[cfg(not(unix))]
+  pub(crate) fn open() -> io::Result<Self> {
+    Err(io::Error::new(
+      io::ErrorKind::Other,
+      "Pseudo-terminals are not supported on this platform",
+    ))
+  }
+
+  /// Stream of the process writing to the terminal
+  pub(crate) fn stdio(&self) -> io::Result<Stdio> {
+    Ok(Stdio::from(self.writer.try_clone()?))
+  }
+}
@@ -1,15 +1,35 @@
-use std::{collections::HashMap, path::PathBuf};
+use crate::{
+  command::{Condition, Dependency, Output, Shell},
+  utils::platform::Platforms,
+};
+use std::{collections::HashMap, path::PathBuf, time::Duration};
+
+/// A command of the group
+#[derive(Debug, Clone)]
+pub enum Entry {
+  /// A command line, or a `wk:` task with its arguments and variables
+  Line(String),
+  /// A task whose arguments and variables are given as they are, not parsed again
+  Task(Dependency),
+}
 
 #[derive(Debug, Clone)]
 pub struct ConcurrentBuilder {
   name: String,
   source: PathBuf,
   hidden: bool,
-  commands: Vec<String>,
+  commands: Vec<Entry>,
   pub(crate) variables: HashMap<String, String>,
+  shared_variables: HashMap<String, String>,
   pub(crate) environments: HashMap<String, String>,
   description: Option<String>,
-  dependencies: Vec<String>,
+  dependencies: Vec<Dependency>,
+  timeout: Option<Duration>,
+  platforms: Platforms,
+  when: Option<Condition>,
+  sequential: bool,
+  shell: Option<Shell>,
+  output: Option<Output>,
 }
 
 impl ConcurrentBuilder {
@@ -20,9 +40,16 @@ impl ConcurrentBuilder {
       hidden: false,
       commands: Vec::new(),
       variables: HashMap::new(),
+      shared_variables: HashMap::new(),
       environments: HashMap::new(),
       description: None,
       dependencies: Vec::new(),
+      timeout: None,
+      platforms: Platforms::default(),
+      when: None,
+      sequential: false,
+      shell: None,
+      output: None,
     }
   }
 
@@ -50,14 +77,74 @@ impl ConcurrentBuilder {
     self
   }
 
+  pub fn source(&self) -> &PathBuf {
+    &self.source
+  }
+
   pub fn with_hidden(&mut self, hidden: bool) -> &mut Self {
     self.hidden = hidden;
     self
   }
 
-  pub fn with_dependency<S>(&mut self, dependency: S) -> &mut Self
+  pub fn with_timeout(&mut self, timeout: Duration) -> &mut Self {
+    self.timeout = Some(timeout);
+    self
+  }
+
+  pub fn timeout(&self) -> Option<Duration> {
+    self.timeout
+  }
+
+  /// Shell running the commands of the group that are not `wk:` tasks
+  pub fn with_shell<S>(&mut self, shell: S) -> &mut Self
   where
+    S: Into<Shell>,
+  {
+    self.shell = Some(shell.into());
+    self
+  }
+
+  pub fn shell(&self) -> Option<&Shell> {
+    self.shell.as_ref()
+  }
+
+  /// Output of the commands of the group that do not set their own
+  pub fn with_output(&mut self, output: Output) -> &mut Self {
+    self.output = Some(output);
+    self
+  }
+
+  pub fn output(&self) -> Option<Output> {
+    self.output
+  }
+
+  /// Run the commands only when the condition is met
+  pub fn with_condition(&mut self, condition: Condition) -> &mut Self {
+    self.when = Some(condition);
+    self
+  }
+
+  pub fn condition(&self) -> Option<&Condition> {
+    self.when.as_ref()
+  }
+
+  /// Restrict the task to a list of platforms, every list added has to match
+  pub fn with_platforms<I, S>(&mut self, platforms: I) -> &mut Self
+  where
+    I: IntoIterator<Item = S>,
     S: Into<String>,
+  {
+    self.platforms.add(platforms);
+    self
+  }
+
+  pub fn platforms(&self) -> &Platforms {
+    &self.platforms
+  }
+
+  pub fn with_dependency<S>(&mut self, dependency: S) -> &mut Self
+  where
+    S: Into<Dependency>,
   {
     self.dependencies.push(dependency.into());
     self
@@ -66,7 +153,7 @@ impl ConcurrentBuilder {
   pub fn with_dependencies<I, S>(&mut self, dependencies: I) -> &mut Self
   where
     I: IntoIterator<Item = S>,
-    S: Into<String>,
+    S: Into<Dependency>,
   {
     for dependency in dependencies {
       self.with_dependency(dependency);
@@ -78,7 +165,12 @@ impl ConcurrentBuilder {
   where
     S: Into<String>,
   {
-    self.commands.push(command.into());
+    self.commands.push(Entry::Line(command.into()));
+    self
+  }
+
+  pub fn with_task(&mut self, dependency: Dependency) -> &mut Self {
+    self.commands.push(Entry::Task(dependency));
     self
   }
 
@@ -93,11 +185,43 @@ impl ConcurrentBuilder {
     self
   }
 
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+
+  pub fn commands(&self) -> &Vec<Entry> {
+    &self.commands
+  }
+
+  pub fn dependencies(&self) -> &Vec<Dependency> {
+    &self.dependencies
+  }
+
   pub fn with_variables(&mut self, variables: HashMap<String, String>) -> &mut Self {
     self.variables.extend(variables);
     self
   }
 
+  /// Variables declared by the task itself, given to its `wk:` commands
+  pub fn with_shared_variables(&mut self, variables: HashMap<String, String>) -> &mut Self {
+    self.shared_variables.extend(variables);
+    self
+  }
+
+  pub fn shared_variables(&self) -> &HashMap<String, String> {
+    &self.shared_variables
+  }
+
+  /// Run the commands one after the other instead of all together
+  pub fn with_sequential(&mut self, sequential: bool) -> &mut Self {
+    self.sequential = sequential;
+    self
+  }
+
+  pub fn is_sequential(&self) -> bool {
+    self.sequential
+  }
+
   pub fn with_environments(&mut self, environments: HashMap<String, String>) -> &mut Self {
     self.environments.extend(environments);
     self
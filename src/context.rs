@@ -1,14 +1,43 @@
 use crate::{
-  command::{Command, CommandBuilder, CommandResult},
+  command::{
+    builtin_variables, exported_variables, replace_variables, Command, CommandBuilder,
+    CommandFuture, CommandResult, Condition, Dependency, Fingerprint, Hooks, Inspection, Output,
+    Service, Shell,
+  },
+  concurrent::{ConcurrentBuilder, Entry},
   error::Error,
   importer::CommandImported,
+  logs::Logs,
+  summary::{self, Status, Summary},
+};
+use crate::{
+  logger, supervisor,
+  utils::{argv::quote, glob, platform, timer, watch},
+};
+use futures::{
+  future::{join_all, select, Either, Future},
+  StreamExt,
+};
+use std::{
+  collections::{HashMap, HashSet},
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
 };
-use std::collections::HashMap;
 
 #[derive(Debug)]
 pub struct Context {
   pub(crate) tasks: HashMap<String, CommandImported>,
-  pub(crate) debug: i32,
+  /// Timeout of the tasks not declaring their own
+  pub(crate) timeout: Option<Duration>,
+  /// Output of the tasks that do not set their own
+  pub(crate) output: Option<Output>,
+  /// Default shell of the file, inherited by the files extending it
+  pub(crate) shell: Option<Shell>,
+  /// Where the runs are logged
+  pub(crate) logs: Logs,
+  /// How the summary is printed at the end of a run
+  pub(crate) summary: summary::Format,
 }
 
 impl Context {
@@ -16,18 +45,55 @@ impl Context {
   where
     S: AsRef<str>,
   {
-    if let Some(imported) = self.tasks.get(name.as_ref()) {
-      match imported {
-        CommandImported::Command(builder) => {
-          return Some(builder);
-        }
-        _ => {}
-      }
+    if let Some(CommandImported::Command(builder)) = self.tasks.get(name.as_ref()) {
+      return Some(builder);
+    }
+
+    None
+  }
+
+  pub fn find_concurrent<S>(&self, name: S) -> Option<&ConcurrentBuilder>
+  where
+    S: AsRef<str>,
+  {
+    if let Some(CommandImported::Concurrent(builder)) = self.tasks.get(name.as_ref()) {
+      return Some(builder);
     }
 
     None
   }
 
+  /// Reject tasks restricted to other platforms
+  pub fn check_platform<S>(&self, name: S) -> Result<(), Error>
+  where
+    S: AsRef<str>,
+  {
+    let name_ref = name.as_ref();
+    let platforms = match self.tasks.get(name_ref) {
+      Some(CommandImported::Command(builder)) => builder.platforms(),
+      Some(CommandImported::Concurrent(builder)) => builder.platforms(),
+      None => return Ok(()),
+    };
+    if platforms.is_available() {
+      return Ok(());
+    }
+
+    let err = format!(
+      "\"{}\" is only available on {} (current platform: {})",
+      name_ref,
+      platforms.names().join(", "),
+      platform::current()
+    );
+    Err(Error::Command(err))
+  }
+
+  pub fn is_available<S>(&self, name: S) -> bool
+  where
+    S: AsRef<str>,
+  {
+    self.check_platform(name).is_ok()
+  }
+
   pub fn create_command<S>(
     &self,
     name: S,
@@ -36,99 +102,1276 @@ impl Context {
   where
     S: AsRef<str>,
   {
-    if let Some(builder) = self.find_builder(name) {
-      return Some(builder.to_command(variables));
+    self.create_instance(&Dependency::new(name.as_ref()), variables)
+  }
+
+  /// Create the command of a dependency, with its arguments and variable overrides
+  fn create_instance(
+    &self,
+    dependency: &Dependency,
+    variables: Option<&HashMap<String, String>>,
+  ) -> Option<Command> {
+    let builder = self.find_builder(&dependency.task)?;
+
+    let mut command = if dependency.args.is_empty() {
+      builder.to_command(variables)
+    } else {
+      builder
+        .clone()
+        .with_args(&dependency.args)
+        .to_command(variables)
+    };
+    command.timeout = command.timeout.or(self.timeout);
+    command.output = command.output.or(self.output);
+    command.arguments = dependency.arguments();
+    command.invocation.dependency = dependency.clone();
+    Some(command)
+  }
+
+  /// Resolve an entry of a concurrent task, either a `wk:` task or a shell command.
+  /// `group` is the invocation of the concurrent task, its arguments label the entry.
+  pub fn create_concurrent_command(
+    &self,
+    concurrent: &ConcurrentBuilder,
+    group: &Dependency,
+    index: usize,
+    variables: Option<&HashMap<String, String>>,
+  ) -> Result<Command, Error> {
+    let vars = group_variables(concurrent, variables);
+
+    let entry = match &concurrent.commands()[index] {
+      Entry::Line(line) => {
+        let line = replace_variables(line, &vars);
+        if line.starts_with("wk:") {
+          Entry::Task(Dependency::from(line.as_str()))
+        } else {
+          Entry::Line(line)
+        }
+      }
+      task => task.clone(),
+    };
+
+    let mut command = match entry {
+      Entry::Task(dependency) => {
+        // Variables of the group, then the command line ones, then the overrides of the entry
+        let mut child_vars = concurrent.shared_variables().clone();
+        if let Some(v) = variables {
+          child_vars.extend(v.clone());
+        }
+        child_vars.extend(dependency.variables.clone());
+
+        match self.create_instance(&dependency, Some(&child_vars)) {
+          Some(mut command) => {
+            self.check_platform(&dependency.task)?;
+            // The output of the group comes before the one of the run
+            command.output = self
+              .find_builder(&dependency.task)
+              .and_then(|builder| builder.output());
+            command
+          }
+          None => {
+            let err = format!(
+              "Command \"{}\" not found in \"{}\"",
+              dependency.task,
+              concurrent.name()
+            );
+            return Err(Error::Command(err));
+          }
+        }
+      }
+      Entry::Line(entry) => {
+        let mut builder = entry.parse::<CommandBuilder>()?;
+        builder
+          .with_name(format!("{}#{}", concurrent.name(), index))
+          .with_source(concurrent.source())
+          .with_variables(concurrent.variables.clone());
+        if let Some(shell) = concurrent.shell() {
+          builder.with_shell(shell.clone());
+        }
+        builder.to_command(variables)
+      }
+    };
+
+    // The timeout of the group bounds each of its commands
+    command.timeout = match (command.timeout.or(self.timeout), concurrent.timeout()) {
+      (Some(timeout), Some(group)) => Some(timeout.min(group)),
+      (timeout, group) => timeout.or(group),
+    };
+
+    // Environments of the concurrent task are used when not set by the task itself
+    for (key, value) in concurrent.environments.iter() {
+      if !command.environments.contains_key(key) {
+        command.environments.insert(key.clone(), value.clone());
+      }
     }
 
-    None
+    command.output = command.output.or(concurrent.output()).or(self.output);
+
+    if concurrent.is_sequential() {
+      command.series = Some(concurrent.name().to_string());
+    } else {
+      command.group = Some(concurrent.name().to_string());
+    }
+
+    // Entries of another invocation of the group are other tasks
+    let mut arguments = group.arguments();
+    arguments.append(&mut command.arguments);
+    command.arguments = arguments;
+
+    command.invocation.entry = Some((group.clone(), index));
+    command.invocation.variables = variables.cloned().unwrap_or_default();
+    Ok(command)
+  }
+
+  /// Resolve a planned command again, with the variables exported by the tasks run before it.
+  /// The variables given to the task are kept over the exported ones.
+  fn resolve(&self, command: Command, scope: &HashMap<String, String>) -> Result<Command, Error> {
+    let mut vars = scope.clone();
+    vars.extend(command.invocation.variables.clone());
+
+    let resolved = match &command.invocation.entry {
+      Some((group, index)) => match self.find_concurrent(&group.task) {
+        Some(concurrent) => {
+          Some(self.create_concurrent_command(concurrent, group, *index, Some(&vars))?)
+        }
+        None => None,
+      },
+      None => self.create_instance(&command.invocation.dependency, Some(&vars)),
+    };
+
+    let mut resolved = match resolved {
+      Some(resolved) => resolved,
+      None => return Ok(command),
+    };
+    resolved.service = command.service;
+
+    // Exported variables are given to the environment too, unless the task sets them
+    for (key, value) in scope.iter() {
+      if !resolved.environments.contains_key(key) {
+        resolved.environments.insert(key.clone(), value.clone());
+      }
+    }
+    Ok(resolved)
+  }
+
+  /// Condition of the command that is not met, if any. The one of its group or series
+  /// is evaluated once for all of its commands, with the variables of the group.
+  fn unmet_condition(
+    &self,
+    command: &Command,
+    groups: &mut HashMap<String, bool>,
+  ) -> Option<Condition> {
+    let entry = command.invocation.entry.as_ref();
+    let group = entry.and_then(|(group, _)| Some((group, self.find_concurrent(&group.task)?)));
+    if let Some((group, concurrent)) = group {
+      if let Some(condition) = concurrent.condition() {
+        let variables = &command.invocation.variables;
+        let met = *groups
+          .entry(invocation_key(&group.task, variables))
+          .or_insert_with(|| {
+            let mut scope = command.clone();
+            scope.variables = group_variables(concurrent, Some(variables));
+            condition.evaluate(&scope)
+          });
+        if !met {
+          return Some(condition.clone());
+        }
+      }
+    }
+
+    match &command.when {
+      Some(when) if !command.is_enabled() => Some(when.clone()),
+      _ => None,
+    }
   }
 
-  pub fn create_stack<'a, S>(
-    &'a self,
+  /// Variables exported by the invocations the command depends on, directly or through
+  /// its group, and by the previous step of its series. What they received is not passed on.
+  fn received(
+    &self,
+    command: &Command,
+    previous: Option<&String>,
+    exported: &HashMap<String, HashMap<String, String>>,
+  ) -> HashMap<String, String> {
+    let mut sources: Vec<String> = command.dependencies.iter().map(|d| d.to_string()).collect();
+    let entry = command.invocation.entry.as_ref();
+    if let Some(concurrent) = entry.and_then(|(group, _)| self.find_concurrent(&group.task)) {
+      sources.extend(concurrent.dependencies().iter().map(|d| d.to_string()));
+    }
+    sources.extend(previous.cloned());
+
+    let mut scope: HashMap<String, String> = HashMap::new();
+    for source in sources {
+      if let Some(variables) = exported.get(&source) {
+        scope.extend(variables.clone());
+      }
+    }
+    scope
+  }
+
+  pub fn create_stack<S>(
+    &self,
     name: S,
-    tasks: &mut Vec<Command<'a>>,
+    tasks: &mut Vec<Command>,
     variables: Option<&HashMap<String, String>>,
-  )
+  ) -> Result<(), Error>
   where
     S: AsRef<str>,
   {
-    let name_ref = name.as_ref();
+    let dependency = Dependency::new(name.as_ref());
+    self.create_dependency_stack(&dependency, tasks, variables, &mut Vec::new())
+  }
+
+  /// Schedule the task after its dependencies. `path` holds the tasks being scheduled,
+  /// from the one asked for to the dependency, finding one of them again is a cycle.
+  fn create_dependency_stack(
+    &self,
+    dependency: &Dependency,
+    tasks: &mut Vec<Command>,
+    variables: Option<&HashMap<String, String>>,
+    path: &mut Vec<String>,
+  ) -> Result<(), Error> {
+    let name_ref = dependency.task.as_str();
+    if path.iter().any(|name| name == name_ref) {
+      let err = format!("Dependency cycle: {} > {}", path.join(" > "), name_ref);
+      return Err(Error::Command(err));
+    }
+
+    path.push(name_ref.to_string());
+    let result = self.schedule(dependency, tasks, variables, path);
+    path.pop();
+    result
+  }
+
+  fn schedule(
+    &self,
+    dependency: &Dependency,
+    tasks: &mut Vec<Command>,
+    variables: Option<&HashMap<String, String>>,
+    path: &mut Vec<String>,
+  ) -> Result<(), Error> {
+    let name_ref = dependency.task.as_str();
+
+    // Variables given by the dependency override the ones of the caller
+    let mut overrides = variables.cloned().unwrap_or_default();
+    overrides.extend(dependency.variables.clone());
+    let variables = if dependency.variables.is_empty() {
+      variables
+    } else {
+      Some(&overrides)
+    };
+
+    if let Some(command) = self.create_instance(dependency, variables) {
+      if is_scheduled(tasks, &command) {
+        verbose!("Skip \"{}\", already scheduled", command.label());
+        return Ok(());
+      }
 
-    if let Some(command) = self.create_command(name_ref, variables) {
       // Add dependencies
-      if !command.dependencies.is_empty() {
-        for depname in command.dependencies {
-          match self.find_builder(&depname) {
-            Some(_dep) => {
-
-              let found = tasks
-              .iter()
-              .filter(|item| &item.name == depname)
-              .take(1)
-              .next();
-
-              if depname != name_ref && found.is_none() {
-                self.create_stack(depname, tasks, variables);
-              }
-            }
-            None => {}
-          }
+      self.create_dependencies(name_ref, &command.dependencies, tasks, variables, path)?;
+
+      if !is_scheduled(tasks, &command) {
+        tasks.push(command);
+      }
+    } else if let Some(concurrent) = self.find_concurrent(name_ref) {
+      let given = variables.cloned().unwrap_or_default();
+      if is_group_scheduled(tasks, name_ref, &given) {
+        verbose!("Skip \"{}\", already scheduled", dependency);
+        return Ok(());
+      }
+
+      // A dependency like `wk:build --var.target=chrome` runs the matrix instances it selects
+      let mut commands: Vec<Command> = Vec::new();
+      for (index, entry) in concurrent.commands().iter().enumerate() {
+        if self.is_selected(entry, &given) {
+          let command = self.create_concurrent_command(concurrent, dependency, index, variables)?;
+          commands.push(command);
         }
       }
+      if commands.is_empty() && !concurrent.commands().is_empty() {
+        let err = format!(
+          "No instance of \"{}\" matches the given variables",
+          name_ref
+        );
+        return Err(Error::Command(err));
+      }
 
-      let found = tasks
-      .iter()
-      .filter(|item| item.name == name_ref)
-      .take(1)
-      .next();
+      // Add dependencies of the concurrent task, then the ones of its commands
+      self.create_dependencies(name_ref, concurrent.dependencies(), tasks, variables, path)?;
+      for command in commands.iter() {
+        path.push(command.name.clone());
+        let result =
+          self.create_dependencies(&command.name, &command.dependencies, tasks, variables, path);
+        path.pop();
+        result?;
+      }
 
-      if found.is_none() {
-        tasks.push(command);
+      if !is_group_scheduled(tasks, name_ref, &given) {
+        tasks.extend(commands);
       }
     }
+
+    Ok(())
   }
 
-  pub async fn run<S>(
+  /// Whether an entry of a group runs with the variables, a matrix instance does when they
+  /// give none of its keys another value
+  fn is_selected(&self, entry: &Entry, variables: &HashMap<String, String>) -> bool {
+    match entry {
+      Entry::Task(dependency) => self
+        .find_builder(&dependency.task)
+        .is_none_or(|builder| builder.matches_combination(variables)),
+      Entry::Line(_) => true,
+    }
+  }
+
+  fn create_dependencies(
+    &self,
+    name: &str,
+    dependencies: &[Dependency],
+    tasks: &mut Vec<Command>,
+    variables: Option<&HashMap<String, String>>,
+    path: &mut Vec<String>,
+  ) -> Result<(), Error> {
+    for dependency in dependencies {
+      let depname = dependency.task.as_str();
+      if !self.tasks.contains_key(depname) {
+        verbose!("Skip \"{}\", dependency of \"{}\" not found", depname, name);
+        continue;
+      }
+
+      // Running the task without one of its dependencies would not be the same task
+      if let Err(Error::Command(err)) = self.check_platform(depname) {
+        let err = format!("Cannot run \"{}\", {}", name, err);
+        return Err(Error::Command(err));
+      }
+
+      if depname == name {
+        verbose!("Skip \"{}\", dependency of itself", dependency);
+        continue;
+      }
+      self.create_dependency_stack(dependency, tasks, variables, path)?;
+    }
+
+    Ok(())
+  }
+
+  pub fn inspect<S>(
     &self,
     name: S,
     variables: Option<&HashMap<String, String>>,
-  ) -> Result<Vec<CommandResult>, Error>
+  ) -> Result<Inspection<'_>, Error>
+  where
+    S: AsRef<str>,
+  {
+    let name_ref = name.as_ref();
+    self.check_platform(name_ref)?;
+
+    if let Some(builder) = self.find_builder(name_ref) {
+      let mut commands: Vec<Command> = Vec::new();
+      self.create_stack(name_ref, &mut commands, variables)?;
+
+      let mut inspection = builder.inspect(variables);
+      inspection.order = commands.iter().map(|c| c.name.to_string()).collect();
+      return Ok(inspection);
+    }
+
+    if let Some(group) = self.find_concurrent(name_ref) {
+      let kind = if group.is_sequential() {
+        "series"
+      } else {
+        "concurrent"
+      };
+      let err = format!(
+        "\"{}\" is a {} task, use --dry-run to print its commands",
+        name_ref, kind
+      );
+      return Err(Error::Command(err));
+    }
+
+    let err = format!("Command \"{}\" not found", name_ref);
+    Err(Error::Command(err))
+  }
+
+  /// Resolve the commands to execute, dependencies first
+  pub fn plan<S>(
+    &self,
+    name: S,
+    variables: Option<&HashMap<String, String>>,
+  ) -> Result<Vec<Command>, Error>
   where
     S: AsRef<str>,
   {
     let name_ref = name.as_ref();
 
-    if let None = self.find_builder(name_ref) {
+    if !self.tasks.contains_key(name_ref) {
       let err = format!("Command \"{}\" not found", name_ref);
       return Err(Error::Command(err));
     }
+    self.check_platform(name_ref)?;
 
     let mut commands: Vec<Command> = Vec::new();
-    self.create_stack(name_ref, &mut commands, variables);
+    self.create_stack(name_ref, &mut commands, variables)?;
+    Ok(commands)
+  }
 
-    // Run commands
+  pub async fn run<S>(
+    &self,
+    name: S,
+    variables: Option<&HashMap<String, String>>,
+  ) -> Result<Vec<CommandResult>, Error>
+  where
+    S: AsRef<str>,
+  {
+    let name_ref = name.as_ref();
+    let commands = self.plan(name_ref, variables)?;
+    self.run_plan(name_ref, commands, variables).await
+  }
+
+  /// Run the planned commands of the task and summarize them
+  async fn run_plan(
+    &self,
+    name_ref: &str,
+    mut commands: Vec<Command>,
+    variables: Option<&HashMap<String, String>>,
+  ) -> Result<Vec<CommandResult>, Error> {
+    // A service asked for directly runs in the foreground, until it exits
+    if let Some(last) = commands.last_mut() {
+      if last.name == name_ref {
+        last.service = false;
+      }
+    }
+
+    let mut summary = self.summarize(&commands);
+
+    // Every scheduled task is summarized whatever the outcome of the run
+    let start = Instant::now();
+    let result = self.run_commands(commands, variables, &mut summary).await;
+    summary.interrupted = matches!(result, Err(Error::Interrupted(_)));
+    summary.finish(start.elapsed().as_secs_f64());
+
+    // Written to stderr, stdout is left to the tasks
+    match self.summary {
+      summary::Format::Json => eprintln!("{}", serde_json::to_string_pretty(&summary)?),
+      summary::Format::Table => {
+        for line in summary.to_string().lines() {
+          logger::log(logger::Level::Normal, format_args!("{}", line));
+        }
+      }
+    }
+
+    result
+  }
+
+  /// Run commands, the ones of a same group concurrently
+  async fn run_commands(
+    &self,
+    commands: Vec<Command>,
+    variables: Option<&HashMap<String, String>>,
+    summary: &mut Summary,
+  ) -> Result<Vec<CommandResult>, Error> {
     let mut results: Vec<CommandResult> = Vec::new();
-    for c in commands.into_iter() {
-      // c.display();
-      // results.push(c.execute().await);
-      match self.debug {
-        2 => {
-          c.display();
+    // Services are stopped once dropped, at the end of the run whatever its outcome
+    let mut services: Vec<Service> = Vec::new();
+    // Each spawned task logs its output, the run is marked as over once dropped
+    let run_log = match self.logs.start() {
+      Ok(run_log) => {
+        verbose!(
+          "Log run {} to {}",
+          run_log.id(),
+          run_log.dir().to_string_lossy()
+        );
+        Some(run_log)
+      }
+      Err(e) => {
+        info!("Cannot log the run: {}", e);
+        None
+      }
+    };
+
+    // Variables exported by the tasks run so far, by task, group and series invocation,
+    // to the ones depending on them and to the next step of their series
+    let mut exported: HashMap<String, HashMap<String, String>> = HashMap::new();
+    // Last step of each series invocation
+    let mut steps: HashMap<String, String> = HashMap::new();
+    // Whether the condition of each group invocation is met, once evaluated
+    let mut groups: HashMap<String, bool> = HashMap::new();
+    let mut commands = commands.into_iter().peekable();
+    while let Some(c) = commands.next() {
+      let mut batch = vec![c];
+      if batch[0].group.is_some() {
+        let group = batch[0].group_label();
+        while let Some(next) = commands.next_if(|n| n.group.is_some() && n.group_label() == group) {
+          batch.push(next);
+        }
+      }
+
+      if let Some(signal) = supervisor::interrupted() {
+        return Err(Error::Interrupted(signal));
+      }
+
+      // Commands are resolved once the tasks they depend on exported their variables
+      let mut inputs: HashMap<String, HashMap<String, String>> = HashMap::new();
+      let mut givers: HashMap<String, Vec<String>> = HashMap::new();
+      let mut resolved: Vec<Command> = Vec::new();
+      for command in batch {
+        let series = command.series.as_ref().and(command.group_label());
+        let previous = series.as_ref().and_then(|series| steps.get(series));
+        let scope = self.received(&command, previous, &exported);
+        let command = if scope.is_empty() {
+          command
+        } else {
+          self.resolve(command, &scope)?
+        };
+
+        if let Some(series) = series {
+          steps.insert(series, command.label());
+        }
+        givers.insert(command.label(), exporting_labels(&command));
+
+        let mut given = scope;
+        if let Some(v) = variables {
+          given.extend(v.clone());
+        }
+        inputs.insert(command.label(), given);
+        resolved.push(command);
+      }
+      let batch = resolved;
+
+      // Conditions are evaluated right before spawning, once dependencies have run.
+      // A skipped task still satisfies the tasks depending on it.
+      let mut enabled: Vec<Command> = Vec::new();
+      for command in batch {
+        match self.unmet_condition(&command, &mut groups) {
+          Some(when) => {
+            verbose!("Skip \"{}\", condition not met: {}", command.label(), when);
+            summary.record(&command.label(), Status::Skipped, None, None);
+          }
+          None => enabled.push(command),
+        }
+      }
+      let batch = enabled;
+
+      // Tasks with sources are skipped when their fingerprint is unchanged and outputs exist
+      let mut fingerprints: HashMap<String, Fingerprint> = HashMap::new();
+      let mut runnables: Vec<Command> = Vec::new();
+      for command in batch {
+        if command.sources.is_empty() {
+          runnables.push(command);
+          continue;
+        }
+
+        let fingerprint = Fingerprint::new(&command)?;
+        if fingerprint.is_fresh(&command) {
+          verbose!("Skip \"{}\", up to date", command.label());
+          summary.record(&command.label(), Status::Cached, None, None);
+        } else {
+          fingerprints.insert(command.label(), fingerprint);
+          runnables.push(command);
+        }
+      }
+
+      if let Some(run_log) = &run_log {
+        for command in runnables.iter_mut() {
+          command.log = run_log.task(&command.label()).ok();
+        }
+      }
+
+      // Services start first, the other commands of the batch may use them
+      let (starting, runnables): (Vec<Command>, Vec<Command>) =
+        runnables.into_iter().partition(|c| c.service);
+
+      let mut outcomes: Vec<Outcome> = Vec::new();
+      for command in starting {
+        let variables = inputs.get(&command.label());
+        let outcome = self.start_service(command, variables, &mut services).await;
+        let ready = outcome.result.is_ok();
+        outcomes.push(outcome);
+        if !ready {
+          break;
+        }
+      }
+
+      if outcomes.iter().all(|outcome| outcome.result.is_ok()) {
+        let runs = runnables.into_iter().map(|c| {
+          let variables = inputs.get(&c.label());
+          self.execute(c, variables)
+        });
+        outcomes.extend(join_all(runs).await);
+      }
+
+      let mut failure: Option<(String, Error)> = None;
+      for outcome in outcomes {
+        let Outcome {
+          label: name,
+          result,
+          elapsed,
+          exports,
+        } = outcome;
+        let (status, code) = Status::of(&result);
+        summary.record(&name, status, code, Some(elapsed));
+        let error = match result {
+          Ok(Some(0)) if services.iter().any(|s| s.label() == name) => {
+            verbose!("Ready \"{}\" in {:.2}s", name, elapsed);
+            results.push(result);
+            continue;
+          }
+          Ok(Some(0)) => {
+            verbose!("Done \"{}\" in {:.2}s", name, elapsed);
+            if let Some(fingerprint) = fingerprints.get(&name) {
+              fingerprint.save()?;
+            }
+            for (key, value) in exports.iter() {
+              verbose!("  export: {}={}", key, quote(value));
+            }
+            for giver in givers.get(&name).into_iter().flatten() {
+              let scope = exported.entry(giver.clone()).or_default();
+              scope.extend(exports.clone());
+            }
+            results.push(result);
+            continue;
+          }
+          Ok(Some(code)) => Error::Failed(name.clone(), code),
+          Ok(None) => Error::Failed(name.clone(), 1),
+          Err(e) => e,
+        };
+
+        match error {
+          Error::Timeout(_, _) => info!("Timed out \"{}\" in {:.2}s", name, elapsed),
+          _ => info!("Failed \"{}\" in {:.2}s", name, elapsed),
         }
-        1 => {
-          c.debug();
-          results.push(c.execute().await)
+        if failure.is_none() {
+          failure = Some((name, error));
         }
-        _ => results.push(c.execute().await),
+      }
+
+      if let Some(signal) = supervisor::interrupted() {
+        return Err(Error::Interrupted(signal));
+      }
+
+      // The tasks using a service that stopped on its own cannot be trusted
+      for service in services.iter_mut() {
+        if let Some(code) = service.exited() {
+          info!("Failed \"{}\", stopped while in use", service.label());
+          let err = format!(
+            "Service \"{}\" exited with code {} after being ready",
+            service.label(),
+            code.unwrap_or(1)
+          );
+          return Err(Error::Command(err));
+        }
+      }
+
+      if let Some((name, error)) = failure {
+        if let Some(run_log) = &run_log {
+          info!(
+            "Read its log with: wk logs \"{}\" --run={}",
+            name,
+            run_log.id()
+          );
+        }
+        return Err(error);
       }
     }
 
     Ok(results)
   }
 
+  /// Summary of the planned commands, each one waiting for its dependencies,
+  /// the ones of its group, and the previous step of its series
+  pub fn summarize(&self, commands: &[Command]) -> Summary {
+    let mut waits = Vec::new();
+    for (index, command) in commands.iter().enumerate() {
+      let mut dependencies = command.dependencies.clone();
+      let group = command.group.as_ref().or(command.series.as_ref());
+      if let Some(concurrent) = group.and_then(|name| self.find_concurrent(name)) {
+        dependencies.extend(concurrent.dependencies().iter().cloned());
+      }
+
+      let mut after: Vec<usize> = commands[..index]
+        .iter()
+        .enumerate()
+        .filter(|(_, c)| {
+          dependencies.iter().any(|d| {
+            (c.name == d.task && c.arguments == d.arguments())
+              || c.group_label() == Some(d.to_string())
+          })
+        })
+        .map(|(i, _)| i)
+        .collect();
+
+      if command.series.is_some() {
+        let previous = commands[..index]
+          .iter()
+          .rposition(|c| c.series.is_some() && c.group_label() == command.group_label());
+        after.extend(previous);
+      }
+      waits.push((command.label(), after));
+    }
+    Summary::new(waits)
+  }
+
+  /// Files watched for a task: the `watch` globs, or else the `sources`, of every planned command
+  pub fn watch_patterns<S>(
+    &self,
+    name: S,
+    variables: Option<&HashMap<String, String>>,
+  ) -> Result<Vec<String>, Error>
+  where
+    S: AsRef<str>,
+  {
+    let mut patterns: Vec<String> = Vec::new();
+    for command in self.plan(name, variables)? {
+      if command.watch.is_empty() {
+        patterns.extend(command.sources);
+      } else {
+        patterns.extend(command.watch);
+      }
+    }
+
+    patterns.sort();
+    patterns.dedup();
+    Ok(patterns)
+  }
+
+  /// Run the task, again each time its watched files change.
+  /// Tasks still running on a change are stopped before the restart.
+  pub async fn watch<S>(
+    &self,
+    name: S,
+    variables: Option<&HashMap<String, String>>,
+  ) -> Result<(), Error>
+  where
+    S: AsRef<str>,
+  {
+    let name_ref = name.as_ref();
+    let patterns = self.watch_patterns(name_ref, variables)?;
+    if patterns.is_empty() {
+      let err = format!(
+        "\"{}\" has nothing to watch, declare its \"watch\" or \"sources\" files",
+        name_ref
+      );
+      return Err(Error::Command(err));
+    }
+
+    let base = std::env::current_dir()?;
+    let mut changes = watch::watch(base.clone(), patterns);
+    let mut commands = self.plan(name_ref, variables)?;
+    loop {
+      let run = Box::pin(self.run_plan(name_ref, commands, variables));
+
+      let changed = match select(run, changes.next()).await {
+        Either::Left((result, _)) => {
+          if let Err(Error::Interrupted(signal)) = result {
+            return Err(Error::Interrupted(signal));
+          }
+          info!("Watching for changes");
+          changes.next().await
+        }
+        Either::Right((Some(changed), run)) => {
+          // The run ends as on an interruption, its pending finally steps still run
+          info!("Changes detected, stopping running tasks");
+          supervisor::cancel();
+          supervisor::stop_all();
+          let _ = run.await;
+          supervisor::resume();
+          Some(changed)
+        }
+        Either::Right((None, run)) => {
+          run.await?;
+          None
+        }
+      };
+
+      match changed {
+        Some(paths) => {
+          for path in paths.iter() {
+            verbose!("  changed: {}", path.to_string_lossy());
+          }
+          commands = self.affected(self.plan(name_ref, variables)?, &base, &paths);
+          let labels: Vec<String> = commands.iter().map(|c| c.label()).collect();
+          info!("Restart {}", labels.join(", "));
+        }
+        None => break,
+      }
+    }
+
+    match supervisor::signal() {
+      Some(signal) => Err(Error::Interrupted(signal)),
+      None => Ok(()),
+    }
+  }
+
+  /// Commands of the plan watching one of the changed paths, and the ones run after them:
+  /// their dependents and the next steps of their series. The whole plan when none matches.
+  /// Tasks exporting variables run again too, their dependents need them.
+  pub(crate) fn affected(
+    &self,
+    commands: Vec<Command>,
+    base: &Path,
+    paths: &[PathBuf],
+  ) -> Vec<Command> {
+    // Tasks, groups and series with a command to run again
+    let mut changed: HashSet<String> = HashSet::new();
+    let mut affected: Vec<bool> = Vec::new();
+    for command in commands.iter() {
+      let patterns = if command.watch.is_empty() {
+        &command.sources
+      } else {
+        &command.watch
+      };
+      let watched = paths.iter().any(|path| {
+        patterns
+          .iter()
+          .any(|pattern| glob::contains(base, pattern, path))
+      });
+
+      let mut dependencies = command.dependencies.iter().collect::<Vec<&Dependency>>();
+      if let Some(concurrent) = command.group.as_ref().and_then(|g| self.find_concurrent(g)) {
+        dependencies.extend(concurrent.dependencies());
+      }
+      let depends = dependencies.iter().any(|d| changed.contains(&d.task))
+        || command.series.as_ref().is_some_and(|s| changed.contains(s));
+
+      if watched || depends {
+        changed.insert(command.name.clone());
+        changed.extend(command.group.clone());
+        changed.extend(command.series.clone());
+      }
+      affected.push(watched || depends);
+    }
+
+    if !affected.contains(&true) {
+      return commands;
+    }
+    commands
+      .into_iter()
+      .zip(affected)
+      .filter(|(command, affected)| *affected || !command.exports.is_empty())
+      .map(|(command, _)| command)
+      .collect()
+  }
+
+  /// Spawn a service and wait for it to be ready. Its hooks wrap the start:
+  /// `after` steps run once it is ready, `finally` ones do not wait for it to stop.
+  async fn start_service(
+    &self,
+    command: Command,
+    variables: Option<&HashMap<String, String>>,
+    services: &mut Vec<Service>,
+  ) -> Outcome {
+    let service = command.clone();
+    self
+      .with_hooks(&command, variables, move || {
+        self.spawn_service(service, variables, services)
+      })
+      .await
+  }
+
+  async fn spawn_service(
+    &self,
+    command: Command,
+    variables: Option<&HashMap<String, String>>,
+    services: &mut Vec<Service>,
+  ) -> Outcome {
+    verbose!("Start \"{}\"", command.label());
+    self.log_command(&command, variables);
+
+    let name = command.label();
+    let start = Instant::now();
+    let result = match Service::start(command) {
+      Ok(mut service) => service.ready().await.map(|_| {
+        services.push(service);
+        Some(0)
+      }),
+      Err(e) => Err(e),
+    };
+
+    Outcome {
+      label: name,
+      result,
+      elapsed: start.elapsed().as_secs_f64(),
+      exports: HashMap::new(),
+    }
+  }
+
+  /// Run the command wrapped by its hooks
+  async fn execute(
+    &self,
+    command: Command,
+    variables: Option<&HashMap<String, String>>,
+  ) -> Outcome {
+    let task = command.clone();
+    self
+      .with_hooks(&command, variables, move || self.spawn(task, variables))
+      .await
+  }
+
+  /// Run the hooks of the command around its main step
+  async fn with_hooks<F, R>(
+    &self,
+    command: &Command,
+    variables: Option<&HashMap<String, String>>,
+    main: F,
+  ) -> Outcome
+  where
+    F: FnOnce() -> R,
+    R: Future<Output = Outcome>,
+  {
+    if command.hooks.is_empty() {
+      return main().await;
+    }
+
+    let start = Instant::now();
+    let hooks = command.hooks.clone();
+
+    let mut result = self
+      .run_hooks(command, "before", &hooks.before, false, variables)
+      .await;
+    let mut exports = HashMap::new();
+    if is_success(&result) {
+      let outcome = main().await;
+      result = outcome.result;
+      exports = outcome.exports;
+      if is_success(&result) {
+        result = self
+          .run_hooks(command, "after", &hooks.after, false, variables)
+          .await;
+      }
+    }
+
+    // Failure steps only notify, the outcome of the task stays the same
+    if !is_success(&result) && supervisor::interrupted().is_none() {
+      let _ = self
+        .run_hooks(command, "on_failure", &hooks.on_failure, false, variables)
+        .await;
+    }
+
+    // Finally steps run whatever the outcome, even once interrupted
+    let cleanup = self
+      .run_hooks(command, "finally", &hooks.finally, true, variables)
+      .await;
+    if is_success(&result) {
+      result = cleanup;
+    }
+
+    Outcome {
+      label: command.label(),
+      result,
+      elapsed: start.elapsed().as_secs_f64(),
+      exports,
+    }
+  }
+
+  /// Run the steps of a hook one after the other, until one fails unless `keep_going`
+  async fn run_hooks(
+    &self,
+    command: &Command,
+    kind: &str,
+    steps: &[String],
+    keep_going: bool,
+    variables: Option<&HashMap<String, String>>,
+  ) -> CommandResult {
+    let mut result: CommandResult = Ok(Some(0));
+
+    for step in steps {
+      if !keep_going {
+        if let Some(signal) = supervisor::interrupted() {
+          return Err(Error::Interrupted(signal));
+        }
+      }
+
+      verbose!("Run {} hook of \"{}\": {}", kind, command.label(), step);
+      let outcome = match self.create_hook_step(command, step, variables) {
+        Ok(step) => step.execute().await,
+        Err(e) => Err(e),
+      };
+
+      if !is_success(&outcome) {
+        info!("Failed {} hook of \"{}\": {}", kind, command.label(), step);
+        if !keep_going {
+          return outcome;
+        }
+        if is_success(&result) {
+          result = outcome;
+        }
+      }
+    }
+
+    result
+  }
+
+  /// A hook step runs a `wk:` task or a shell command with the shell, cwd and
+  /// environments of the task. Steps run as they are, without their own hooks.
+  fn create_hook_step(
+    &self,
+    command: &Command,
+    step: &str,
+    variables: Option<&HashMap<String, String>>,
+  ) -> Result<Command, Error> {
+    let mut hook = if step.starts_with("wk:") {
+      let dependency = Dependency::from(step);
+      let mut vars = variables.cloned().unwrap_or_default();
+      vars.extend(dependency.variables.clone());
+
+      match self.create_instance(&dependency, Some(&vars)) {
+        Some(hook) => hook,
+        None => {
+          let err = format!(
+            "Command \"{}\" not found in the hooks of \"{}\"",
+            dependency.task, command.name
+          );
+          return Err(Error::Command(err));
+        }
+      }
+    } else {
+      // The step replaces the command, not the script or exec of the task
+      let mut hook = command.clone();
+      hook.args = vec![step.to_string()];
+      hook.script = None;
+      hook.exec = None;
+      hook
+    };
+
+    hook.hooks = Hooks::default();
+    hook.retry = None;
+    hook.when = None;
+    hook.service = false;
+    hook.exports = Vec::new();
+    hook.log = command.log.clone();
+    Ok(hook)
+  }
+
+  /// Spawn the command, again while its retry policy allows it
+  async fn spawn(&self, command: Command, variables: Option<&HashMap<String, String>>) -> Outcome {
+    verbose!("Run \"{}\"", command.label());
+    self.log_command(&command, variables);
+
+    let start = Instant::now();
+    let mut attempt = 1;
+    loop {
+      let stdout = Arc::new(Mutex::new(String::new()));
+      let result = if command.exports.is_empty() {
+        command.execute().await
+      } else {
+        CommandFuture::capture(&command, stdout.clone()).await
+      };
+
+      // The stdout of a failed task is shown, it exports nothing
+      if !is_success(&result) && !command.exports.is_empty() {
+        print!("{}", stdout.lock().unwrap_or_else(|e| e.into_inner()));
+      }
+
+      if let Some(retry) = &command.retry {
+        if supervisor::interrupted().is_none() && retry.should_retry(attempt, &result) {
+          let delay = retry.delay(attempt);
+          attempt += 1;
+          info!(
+            "Retry \"{}\" in {:?} (attempt {}/{})",
+            command.label(),
+            delay,
+            attempt,
+            retry.attempts
+          );
+          timer::delay_until(delay, || supervisor::interrupted().is_some()).await;
+
+          // Ctrl-C during the delay stops the task instead of starting another attempt
+          if let Some(signal) = supervisor::interrupted() {
+            return Outcome {
+              label: command.label(),
+              result: Err(Error::Interrupted(signal)),
+              elapsed: start.elapsed().as_secs_f64(),
+              exports: HashMap::new(),
+            };
+          }
+          continue;
+        }
+      }
+
+      // Variables are exported once the task succeeded
+      let (result, exports) = match result {
+        Ok(Some(0)) if !command.exports.is_empty() => {
+          let stdout = stdout.lock().unwrap_or_else(|e| e.into_inner());
+          match exported_variables(&command.label(), &command.exports, &stdout) {
+            Ok(exports) => (result, exports),
+            Err(e) => (Err(e), HashMap::new()),
+          }
+        }
+        result => (result, HashMap::new()),
+      };
+
+      return Outcome {
+        label: command.label(),
+        result,
+        elapsed: start.elapsed().as_secs_f64(),
+        exports,
+      };
+    }
+  }
+
+  fn log_command(&self, command: &Command, variables: Option<&HashMap<String, String>>) {
+    if let Some(cwd) = &command.cwd {
+      verbose!("  cwd: {}", cwd.to_string_lossy());
+    }
+    verbose!("  shell: {}", command.shell.to_string_lossy());
+
+    let argv: Vec<String> = command.argv().iter().map(|arg| quote(arg)).collect();
+    verbose!("  argv: {}", argv.join(" "));
+
+    if logger::enabled(logger::Level::Debug) {
+      if let Some(builder) = self.find_builder(&command.name) {
+        let mut vars: Vec<_> = builder.resolve_variables(variables).into_iter().collect();
+        vars.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for (key, (value, origin)) in vars {
+          debug!("  var {}={} ({})", key, value, origin);
+        }
+      }
+    }
+  }
+
+  /// Outputs and fingerprints of a task, or of every task, that `wk clean` removes.
+  /// Paths that are not inside the directory of the task file are refused.
+  pub fn clean_targets(
+    &self,
+    name: Option<&str>,
+    variables: Option<&HashMap<String, String>>,
+  ) -> Result<Vec<PathBuf>, Error> {
+    let mut commands: Vec<Command> = Vec::new();
+    match name {
+      Some(name) => {
+        self.check_platform(name)?;
+        if let Some(command) = self.create_command(name, variables) {
+          commands.push(command);
+        } else if let Some(concurrent) = self.find_concurrent(name) {
+          for index in 0..concurrent.commands().len() {
+            let group = Dependency::new(name);
+            commands.push(self.create_concurrent_command(concurrent, &group, index, variables)?);
+          }
+        } else {
+          let err = format!("Command \"{}\" not found", name);
+          return Err(Error::Command(err));
+        }
+      }
+      None => {
+        for name in self.tasks.keys() {
+          if self.is_available(name) {
+            commands.extend(self.create_command(name, variables));
+          }
+        }
+      }
+    }
+
+    let mut targets: Vec<PathBuf> = Vec::new();
+    for command in commands.iter().filter(|c| !c.outputs.is_empty()) {
+      let root = match command.source.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.canonicalize()?,
+        _ => std::env::current_dir()?,
+      };
+
+      let base = command.cwd.clone().unwrap_or_default();
+      let mut paths: Vec<PathBuf> = Vec::new();
+      for pattern in command.outputs.iter() {
+        if pattern.trim().is_empty() {
+          let err = format!("\"{}\" has an empty output", command.name);
+          return Err(Error::Command(err));
+        }
+
+        let path = base.join(pattern);
+        if !glob::is_pattern(pattern) && path.is_dir() {
+          paths.push(path);
+        } else {
+          paths.extend(glob::expand(&base, pattern)?);
+        }
+      }
+
+      // Only what is below the directory of the task file can be removed, not the directory itself
+      for path in paths {
+        let canonical = path.canonicalize()?;
+        if canonical == root || !canonical.starts_with(&root) {
+          let err = format!(
+            "Refusing to remove \"{}\" of \"{}\", it is not inside {}",
+            path.to_string_lossy(),
+            command.name,
+            root.to_string_lossy()
+          );
+          return Err(Error::Command(err));
+        }
+        targets.push(path);
+      }
+
+      let fingerprint = Fingerprint::path(command);
+      if fingerprint.exists() {
+        targets.push(fingerprint);
+      }
+    }
+
+    targets.sort();
+    targets.dedup();
+    Ok(targets)
+  }
+
   pub fn extend(&mut self, context: Context) {
     for task in context.tasks {
       self.tasks.insert(task.0, task.1);
     }
   }
 }
+
+/// Labels the variables exported by the command are given under: its invocation and the one
+/// of its group or series
+fn exporting_labels(command: &Command) -> Vec<String> {
+  let mut labels = vec![command.label()];
+  labels.extend(command.group_label());
+  labels
+}
+
+/// What the run of a task left
+struct Outcome {
+  label: String,
+  result: CommandResult,
+  elapsed: f64,
+  /// Variables exported for the tasks depending on it
+  exports: HashMap<String, String>,
+}
+
+fn is_success(result: &CommandResult) -> bool {
+  matches!(result, Ok(Some(0)))
+}
+
+/// A same task with other arguments or variables is another invocation, scheduled separately
+fn is_scheduled(tasks: &[Command], command: &Command) -> bool {
+  tasks.iter().any(|item| item.is_same_invocation(command))
+}
+
+/// Variables of the commands of a group: the builtin ones, the group ones, then the given ones
+fn group_variables(
+  concurrent: &ConcurrentBuilder,
+  variables: Option<&HashMap<String, String>>,
+) -> HashMap<String, String> {
+  let mut vars = builtin_variables();
+  vars.extend(concurrent.variables.clone());
+  if let Some(v) = variables {
+    vars.extend(v.clone());
+  }
+  vars
+}
+
+/// Name of a task with the variables it is given, in a stable order
+fn invocation_key(name: &str, variables: &HashMap<String, String>) -> String {
+  let mut variables: Vec<_> = variables.iter().collect();
+  variables.sort();
+  let mut key = name.to_string();
+  for (key_name, value) in variables {
+    key.push_str(&format!(" --var.{}={}", key_name, value));
+  }
+  key
+}
+
+/// A same group or series with other variables is another invocation, scheduled separately
+fn is_group_scheduled(tasks: &[Command], name: &str, variables: &HashMap<String, String>) -> bool {
+  tasks.iter().any(|item| match &item.invocation.entry {
+    Some((group, _)) => group.task == name && &item.invocation.variables == variables,
+    None => false,
+  })
+}
@@ -1,10 +1,28 @@
-use std::{fmt, io, str::Utf8Error};
+use std::{fmt, io, str::Utf8Error, time::Duration};
 
 #[derive(Debug)]
 pub enum Error {
   Std(Box<dyn std::error::Error>),
   Import(String),
   Command(String),
+  Failed(String, i32),
+  /// Failure of a script, with the line it failed at
+  Script(String, i32, String),
+  Interrupted(i32),
+  Timeout(String, Duration),
+}
+
+impl Error {
+  /// Status wk exits with when this error stops the run
+  pub fn exit_code(&self) -> i32 {
+    match self {
+      Error::Failed(_, code) if *code != 0 => *code,
+      Error::Script(_, code, _) if *code != 0 => *code,
+      Error::Interrupted(signal) => 128 + signal,
+      Error::Timeout(_, _) => 124,
+      _ => 1,
+    }
+  }
 }
 
 impl std::fmt::Display for Error {
@@ -12,6 +30,18 @@ impl std::fmt::Display for Error {
     match self {
       Error::Import(s) => write!(f, "[Import] {}", s),
       Error::Command(s) => write!(f, "[Command] {}", s),
+      Error::Failed(name, code) => write!(f, "[Failed] \"{}\" exited with code {}", name, code),
+      Error::Script(name, code, line) => write!(
+        f,
+        "[Failed] \"{}\" exited with code {} at {}",
+        name, code, line
+      ),
+      Error::Interrupted(signal) => write!(f, "[Interrupted] Stopped by signal {}", signal),
+      Error::Timeout(name, duration) => write!(
+        f,
+        "[Timeout] \"{}\" did not finish within {:?}",
+        name, duration
+      ),
       Error::Std(e) => write!(f, "[Std] {}", e),
     }
   }
@@ -31,6 +61,12 @@ impl From<serde_yaml::Error> for Error {
   }
 }
 
+impl From<serde_json::Error> for Error {
+  fn from(value: serde_json::Error) -> Self {
+    Error::Std(Box::new(value))
+  }
+}
+
 impl From<Utf8Error> for Error {
   fn from(value: Utf8Error) -> Self {
     Error::Std(Box::new(value))
@@ -1,3 +1,6 @@
+#![allow(clippy::redundant_static_lifetimes, clippy::needless_borrows_for_generic_args)]
+#![allow(clippy::while_let_on_iterator)]
+
 use crate::error::Error;
 use std::{path::Path, path::PathBuf};
 
@@ -1,10 +1,17 @@
+#![allow(clippy::needless_return)]
+
 use crate::{
-  command::CommandBuilder, concurrent::ConcurrentBuilder, context::Context, error::Error,
-  utils::fs::Reader,
+  command::{
+    cache_dir, Backoff, CommandBuilder, Dependency, Export, Hooks, Origin, Output,
+    Probe, Retry, Shell,
+  }, concurrent::ConcurrentBuilder, context::Context, error::Error,
+  logs::{self, Logs},
+  utils::{argv, duration, fs::Reader, platform},
 };
+use regex::Regex;
 use serde::Deserialize;
-use serde_yaml;
 use std::collections::HashMap;
+use std::convert::{TryFrom, TryInto};
 use std::path::{Path, PathBuf};
 
 type Dictionary<T> = HashMap<String, T>;
@@ -12,57 +19,191 @@ type Dictionary<T> = HashMap<String, T>;
 #[derive(Deserialize, Debug)]
 struct CommandsFile {
   extends: Option<Vec<PathBuf>>,
+  platforms: Option<Vec<String>>,
+  shell: Option<ShellDescription>,
+  keep_logs: Option<usize>,
   commands: Dictionary<CommandFileDescription>,
   variables: Option<Dictionary<Primitive>>,
   environments: Option<Dictionary<Primitive>>,
+  #[serde(flatten)]
+  hooks: HooksDescription,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(untagged)]
 enum CommandFileDescription {
   StringCommand(String),
-  Command(CommandDescription),
   ExtendedCommand(ExtendedCommandDescription),
   Concurrent(ConcurrentDescription),
+  Series(SeriesDescription),
+  // Last of the objects, a script task has no command
+  Command(CommandDescription),
+  List(Vec<String>),
 }
 
 #[derive(Deserialize, Debug)]
 struct CommandDescription {
   cwd: Option<PathBuf>,
   args: Option<Vec<String>>,
-  shell: Option<PathBuf>,
+  shell: Option<ShellDescription>,
   hidden: Option<bool>,
+  #[serde(default)]
   command: String,
-  depends: Option<Vec<String>>,
+  script: Option<String>,
+  interpreter: Option<String>,
+  exec: Option<Vec<String>>,
+  output: Option<Output>,
+  outputs_var: Option<String>,
+  export: Option<Dictionary<String>>,
+  depends: Option<Vec<DependencyDescription>>,
   variables: Option<Dictionary<Primitive>>,
   environments: Option<Dictionary<Primitive>>,
   description: Option<String>,
+  timeout: Option<Primitive>,
+  retry: Option<RetryDescription>,
+  platforms: Option<Vec<String>>,
+  overrides: Option<Dictionary<OverrideDescription>>,
+  when: Option<Primitive>,
+  sources: Option<Vec<String>>,
+  outputs: Option<Vec<String>>,
+  watch: Option<Vec<String>>,
+  service: Option<bool>,
+  ready: Option<ReadyDescription>,
+  matrix: Option<Dictionary<Vec<Primitive>>>,
+  parallel: Option<bool>,
+  #[serde(flatten)]
+  hooks: HooksDescription,
 }
 
 #[derive(Deserialize, Debug)]
 struct ExtendedCommandDescription {
   cwd: Option<PathBuf>,
   args: Option<Vec<String>>,
-  shell: Option<PathBuf>,
+  shell: Option<ShellDescription>,
   hidden: Option<bool>,
   extend: String,
-  depends: Option<Vec<String>>,
+  // Command of the platform override, replacing the one of the extended task
+  #[serde(skip)]
+  command: Option<String>,
+  script: Option<String>,
+  interpreter: Option<String>,
+  exec: Option<Vec<String>>,
+  output: Option<Output>,
+  outputs_var: Option<String>,
+  export: Option<Dictionary<String>>,
+  depends: Option<Vec<DependencyDescription>>,
   variables: Option<Dictionary<Primitive>>,
   description: Option<String>,
   environments: Option<Dictionary<Primitive>>,
+  timeout: Option<Primitive>,
+  retry: Option<RetryDescription>,
+  platforms: Option<Vec<String>>,
+  overrides: Option<Dictionary<OverrideDescription>>,
+  when: Option<Primitive>,
+  sources: Option<Vec<String>>,
+  outputs: Option<Vec<String>>,
+  watch: Option<Vec<String>>,
+  service: Option<bool>,
+  ready: Option<ReadyDescription>,
+  matrix: Option<Dictionary<Vec<Primitive>>>,
+  parallel: Option<bool>,
+  #[serde(flatten)]
+  hooks: HooksDescription,
 }
 
 #[derive(Deserialize, Debug)]
 struct ConcurrentDescription {
-  hidden: Option<bool>,
-  depends: Option<Vec<String>>,
   commands: Vec<String>,
+  #[serde(flatten)]
+  group: GroupDescription,
+}
+
+#[derive(Deserialize, Debug)]
+struct SeriesDescription {
+  series: Vec<String>,
+  #[serde(flatten)]
+  group: GroupDescription,
+}
+
+/// Fields shared by the concurrent and the series tasks
+#[derive(Deserialize, Debug, Default)]
+struct GroupDescription {
+  hidden: Option<bool>,
+  depends: Option<Vec<DependencyDescription>>,
   variables: Option<Dictionary<Primitive>>,
   description: Option<String>,
   environments: Option<Dictionary<Primitive>>,
+  timeout: Option<Primitive>,
+  platforms: Option<Vec<String>>,
+  when: Option<Primitive>,
+  output: Option<Output>,
+}
+
+/// A shell program, or `false` to spawn the command directly
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum ShellDescription {
+  Enabled(bool),
+  Program(PathBuf),
+  Custom {
+    program: PathBuf,
+    args: Option<Vec<String>>,
+  },
+}
+
+/// Steps wrapping a task, declared on the task or on the file for all of its tasks
+#[derive(Deserialize, Debug, Default)]
+struct HooksDescription {
+  before: Option<Vec<String>>,
+  after: Option<Vec<String>>,
+  on_failure: Option<Vec<String>>,
+  finally: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct OverrideDescription {
+  command: Option<String>,
+  shell: Option<ShellDescription>,
+  environments: Option<Dictionary<Primitive>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum DependencyDescription {
+  Reference(String),
+  Task {
+    task: String,
+    args: Option<Vec<String>>,
+    variables: Option<Dictionary<Primitive>>,
+  },
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+enum ReadyDescription {
+  Port(u16),
+  Stdout(String),
+  File(String),
+  Command(String),
+}
+
+#[derive(Deserialize, Debug)]
+struct RetryDescription {
+  attempts: u32,
+  delay: Option<Primitive>,
+  backoff: Option<BackoffDescription>,
+  on_exit_codes: Option<Vec<i32>>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "lowercase")]
+enum BackoffDescription {
+  Fixed,
+  Exponential,
 }
 
 pub struct ExtendedCommand {
+  name: String,
   extend: CommandBuilder,
   desc: ExtendedCommandDescription,
 }
@@ -78,6 +219,7 @@ enum Primitive {
 }
 
 #[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
 pub enum CommandImported {
   Command(CommandBuilder),
   Concurrent(ConcurrentBuilder),
@@ -85,25 +227,53 @@ pub enum CommandImported {
 
 pub(crate) struct Resolver {
   source: PathBuf,
+  extended: bool,
   tasks: Dictionary<CommandImported>,
   extended_tasks: Vec<(String, ExtendedCommandDescription)>,
   extends: Option<Vec<PathBuf>>,
+  platforms: Vec<String>,
+  shell: Option<Shell>,
+  keep_logs: usize,
+  hooks: Hooks,
   commands: Dictionary<CommandFileDescription>,
   variables: Dictionary<Primitive>,
   environments: Dictionary<Primitive>,
 }
 
-impl From<CommandDescription> for CommandBuilder {
-  fn from(value: CommandDescription) -> Self {
+impl TryFrom<CommandDescription> for CommandBuilder {
+  type Error = Error;
+
+  fn try_from(value: CommandDescription) -> Result<Self, Self::Error> {
     let mut task = CommandBuilder::new();
-    task.with_command(value.command).with_cwd(value.cwd);
+    task.with_command(&value.command).with_cwd(value.cwd);
 
+    if let Some(exec) = value.exec {
+      task.with_exec(exec);
+    }
+    if let Some(output) = value.output {
+      task.with_output(output);
+    }
+    if let Some(name) = value.outputs_var {
+      task.with_export(name, Export::Stdout);
+    }
+    for (name, source) in value.export.unwrap_or_default() {
+      task.with_export(name, Export::from(source.as_str()));
+    }
+    match value.shell {
+      Some(ShellDescription::Enabled(false)) if task.exec().is_none() => {
+        // Split the original command, its quoted whitespaces are kept
+        task.with_exec(argv::split(&value.command));
+      }
+      Some(shell) => {
+        if let Some(shell) = shell.into_shell() {
+          task.with_shell(shell);
+        }
+      }
+      None => {}
+    }
     if let Some(args) = value.args {
       task.with_args(args);
     }
-    if let Some(shell) = value.shell {
-      task.with_shell(shell);
-    }
     if let Some(hidden) = value.hidden {
       task.with_hidden(hidden);
     }
@@ -119,15 +289,78 @@ impl From<CommandDescription> for CommandBuilder {
     if let Some(description) = value.description {
       task.with_description(description);
     }
+    if let Some(timeout) = parse_field("timeout", value.timeout, duration::parse)? {
+      task.with_timeout(timeout);
+    }
+    if let Some(retry) = value.retry {
+      task.with_retry(retry.try_into()?);
+    }
+    if let Some(platforms) = value.platforms {
+      task.with_platforms(platforms);
+    }
+    if let Some(when) = parse_field("when", value.when, str::parse)? {
+      task.with_condition(when);
+    }
+    if let Some(sources) = value.sources {
+      task.with_sources(sources);
+    }
+    if let Some(outputs) = value.outputs {
+      task.with_outputs(outputs);
+    }
+    if let Some(watch) = value.watch {
+      task.with_watch(watch);
+    }
+    if let Some(service) = value.service {
+      task.with_service(service);
+    }
+    if let Some(ready) = value.ready {
+      task.with_ready(ready.try_into()?);
+    }
+    if let Some(matrix) = value.matrix {
+      task.with_matrix(matrix_to_s(matrix));
+    }
+    if let Some(parallel) = value.parallel {
+      task.with_parallel(parallel);
+    }
+    if let Some(hooks) = value.hooks.into_hooks() {
+      task.with_hooks(hooks);
+    }
+    if let Some(script) = value.script {
+      task.with_script(script);
+    }
+    if let Some(interpreter) = value.interpreter {
+      task.with_interpreter(interpreter);
+    }
 
-    return task;
+    return Ok(task);
   }
 }
 
-impl From<ConcurrentDescription> for ConcurrentBuilder {
-  fn from(value: ConcurrentDescription) -> Self {
-    let mut concurrent = ConcurrentBuilder::new();
+impl TryFrom<ConcurrentDescription> for ConcurrentBuilder {
+  type Error = Error;
+
+  fn try_from(value: ConcurrentDescription) -> Result<Self, Self::Error> {
+    let mut concurrent: ConcurrentBuilder = value.group.try_into()?;
     concurrent.with_commands(value.commands);
+    return Ok(concurrent);
+  }
+}
+
+impl TryFrom<SeriesDescription> for ConcurrentBuilder {
+  type Error = Error;
+
+  fn try_from(value: SeriesDescription) -> Result<Self, Self::Error> {
+    let mut series: ConcurrentBuilder = value.group.try_into()?;
+    series.with_commands(value.series).with_sequential(true);
+    return Ok(series);
+  }
+}
+
+impl TryFrom<GroupDescription> for ConcurrentBuilder {
+  type Error = Error;
+
+  fn try_from(value: GroupDescription) -> Result<Self, Self::Error> {
+    let mut concurrent = ConcurrentBuilder::new();
 
     if let Some(hidden) = value.hidden {
       concurrent.with_hidden(hidden);
@@ -144,22 +377,59 @@ impl From<ConcurrentDescription> for ConcurrentBuilder {
     if let Some(environments) = value.environments {
       concurrent.with_environments(p_to_s(environments));
     }
+    if let Some(timeout) = parse_field("timeout", value.timeout, duration::parse)? {
+      concurrent.with_timeout(timeout);
+    }
+    if let Some(platforms) = value.platforms {
+      concurrent.with_platforms(platforms);
+    }
+    if let Some(when) = parse_field("when", value.when, str::parse)? {
+      concurrent.with_condition(when);
+    }
+    if let Some(output) = value.output {
+      concurrent.with_output(output);
+    }
 
-    return concurrent;
+    return Ok(concurrent);
   }
 }
 
-impl From<ExtendedCommand> for CommandBuilder {
-  fn from(value: ExtendedCommand) -> Self {
+impl TryFrom<ExtendedCommand> for CommandBuilder {
+  type Error = Error;
+
+  fn try_from(value: ExtendedCommand) -> Result<Self, Self::Error> {
     let mut task = value.extend;
-    task.with_cwd(value.desc.cwd);
+    task.with_name(value.name).with_cwd(value.desc.cwd);
 
+    if let Some(command) = value.desc.command {
+      task.with_command(command);
+    }
+    if let Some(exec) = value.desc.exec {
+      task.with_exec(exec);
+    }
+    if let Some(output) = value.desc.output {
+      task.with_output(output);
+    }
+    if let Some(name) = value.desc.outputs_var {
+      task.with_export(name, Export::Stdout);
+    }
+    for (name, source) in value.desc.export.unwrap_or_default() {
+      task.with_export(name, Export::from(source.as_str()));
+    }
+    match value.desc.shell {
+      Some(ShellDescription::Enabled(false)) => {
+        task.without_shell();
+      }
+      Some(shell) => {
+        if let Some(shell) = shell.into_shell() {
+          task.with_shell(shell);
+        }
+      }
+      None => {}
+    }
     if let Some(args) = value.desc.args {
       task.with_args(args);
     }
-    if let Some(shell) = value.desc.shell {
-      task.with_shell(shell);
-    }
     if let Some(hidden) = value.desc.hidden {
       task.with_hidden(hidden);
     }
@@ -175,8 +445,50 @@ impl From<ExtendedCommand> for CommandBuilder {
     if let Some(description) = value.desc.description {
       task.with_description(description);
     }
+    if let Some(timeout) = parse_field("timeout", value.desc.timeout, duration::parse)? {
+      task.with_timeout(timeout);
+    }
+    if let Some(retry) = value.desc.retry {
+      task.with_retry(retry.try_into()?);
+    }
+    if let Some(platforms) = value.desc.platforms {
+      task.with_platforms(platforms);
+    }
+    if let Some(when) = parse_field("when", value.desc.when, str::parse)? {
+      task.with_condition(when);
+    }
+    if let Some(sources) = value.desc.sources {
+      task.with_sources(sources);
+    }
+    if let Some(outputs) = value.desc.outputs {
+      task.with_outputs(outputs);
+    }
+    if let Some(watch) = value.desc.watch {
+      task.with_watch(watch);
+    }
+    if let Some(service) = value.desc.service {
+      task.with_service(service);
+    }
+    if let Some(ready) = value.desc.ready {
+      task.with_ready(ready.try_into()?);
+    }
+    if let Some(matrix) = value.desc.matrix {
+      task.with_matrix(matrix_to_s(matrix));
+    }
+    if let Some(parallel) = value.desc.parallel {
+      task.with_parallel(parallel);
+    }
+    if let Some(hooks) = value.desc.hooks.into_hooks() {
+      task.with_hooks(hooks);
+    }
+    if let Some(script) = value.desc.script {
+      task.with_script(script);
+    }
+    if let Some(interpreter) = value.desc.interpreter {
+      task.with_interpreter(interpreter);
+    }
 
-    return task;
+    return Ok(task);
   }
 }
 
@@ -201,7 +513,7 @@ impl From<CommandDescription> for ExtendedCommandDescription {
         extend = params[0].to_string();
         match value.args.take() {
           Some(mut a) => {
-            a.extend((&params[1..]).to_vec());
+            a.extend(params[1..].to_vec());
             value.args = Some(a);
           }
           None => {
@@ -213,6 +525,7 @@ impl From<CommandDescription> for ExtendedCommandDescription {
 
     ExtendedCommandDescription {
       extend,
+      command: None,
       args: value.args,
       cwd: value.cwd,
       shell: value.shell,
@@ -221,10 +534,126 @@ impl From<CommandDescription> for ExtendedCommandDescription {
       variables: value.variables,
       environments: value.environments,
       description: value.description,
+      timeout: value.timeout,
+      retry: value.retry,
+      platforms: value.platforms,
+      overrides: value.overrides,
+      when: value.when,
+      sources: value.sources,
+      outputs: value.outputs,
+      watch: value.watch,
+      service: value.service,
+      ready: value.ready,
+      matrix: value.matrix,
+      parallel: value.parallel,
+      hooks: value.hooks,
+      script: value.script,
+      interpreter: value.interpreter,
+      exec: value.exec,
+      output: value.output,
+      outputs_var: value.outputs_var,
+      export: value.export,
     }
   }
 }
 
+impl HooksDescription {
+  /// None when no hook is declared, so that the ones of an extended task are kept
+  fn into_hooks(self) -> Option<Hooks> {
+    if self.before.is_none()
+      && self.after.is_none()
+      && self.on_failure.is_none()
+      && self.finally.is_none()
+    {
+      return None;
+    }
+
+    Some(Hooks {
+      before: self.before.unwrap_or_default(),
+      after: self.after.unwrap_or_default(),
+      on_failure: self.on_failure.unwrap_or_default(),
+      finally: self.finally.unwrap_or_default(),
+    })
+  }
+}
+
+impl ShellDescription {
+  /// None for the default shell, or when disabled
+  /// `$SHELL` stands for the shell of the user
+  fn into_shell(self) -> Option<Shell> {
+    match self {
+      ShellDescription::Enabled(_) => None,
+      ShellDescription::Program(program) if program.as_os_str() == "$SHELL" => {
+        Some(Shell::user())
+      }
+      ShellDescription::Program(program) => Some(Shell::preset(program)),
+      ShellDescription::Custom {
+        program,
+        args: Some(args),
+      } => Some(Shell::new(program, args)),
+      ShellDescription::Custom {
+        program,
+        args: None,
+      } => Some(Shell::preset(program)),
+    }
+  }
+}
+
+impl From<DependencyDescription> for Dependency {
+  fn from(value: DependencyDescription) -> Self {
+    match value {
+      DependencyDescription::Reference(reference) => reference.into(),
+      DependencyDescription::Task {
+        task,
+        args,
+        variables,
+      } => {
+        let mut dependency: Dependency = task.into();
+        dependency.args.extend(args.unwrap_or_default());
+        dependency
+          .variables
+          .extend(p_to_s(variables.unwrap_or_default()));
+        dependency
+      }
+    }
+  }
+}
+
+impl TryFrom<ReadyDescription> for Probe {
+  type Error = Error;
+
+  fn try_from(value: ReadyDescription) -> Result<Self, Self::Error> {
+    let probe = match value {
+      ReadyDescription::Port(port) => Probe::Port(port),
+      ReadyDescription::Stdout(regex) => {
+        let regex = Regex::new(&regex).map_err(|e| Error::Import(e.to_string()));
+        Probe::Stdout(regex.map_err(|e| field_error("ready.stdout", e))?)
+      }
+      ReadyDescription::File(path) => Probe::File(path),
+      ReadyDescription::Command(script) => Probe::Command(script),
+    };
+    Ok(probe)
+  }
+}
+
+impl TryFrom<RetryDescription> for Retry {
+  type Error = Error;
+
+  fn try_from(value: RetryDescription) -> Result<Self, Self::Error> {
+    let backoff = match value.backoff {
+      Some(BackoffDescription::Exponential) => Backoff::Exponential,
+      _ => Backoff::Fixed,
+    };
+
+    Ok(Retry {
+      attempts: value.attempts.max(1),
+      delay: parse_field("retry.delay", value.delay, duration::parse)?.unwrap_or_default(),
+      backoff,
+      on_exit_codes: value.on_exit_codes.unwrap_or_default(),
+    })
+  }
+}
+
 impl From<Primitive> for String {
   fn from(value: Primitive) -> Self {
     match value {
@@ -249,7 +678,7 @@ impl std::str::FromStr for CommandDescription {
     let args: Vec<&str> = s.split_whitespace().collect();
     let mut args: Vec<String> = args.iter().map(|s| (*s).into()).collect();
 
-    if args.len() == 0 {
+    if args.is_empty() {
       return Err(Error::Command(
         "Cannot convert an empty string to command description".to_string(),
       ));
@@ -266,12 +695,45 @@ impl std::str::FromStr for CommandDescription {
       variables: None,
       environments: None,
       description: None,
+      timeout: None,
+      retry: None,
+      platforms: None,
+      overrides: None,
+      when: None,
+      sources: None,
+      outputs: None,
+      watch: None,
+      service: None,
+      ready: None,
+      matrix: None,
+      parallel: None,
+      hooks: HooksDescription::default(),
+      script: None,
+      interpreter: None,
+      exec: None,
+      output: None,
+      outputs_var: None,
+      export: None,
     })
   }
 }
 
 impl Resolver {
+  fn origin(&self) -> Origin {
+    if self.extended {
+      Origin::Extends(self.source.clone())
+    } else {
+      Origin::File(self.source.clone())
+    }
+  }
+
   pub fn resolve(mut self) -> Result<Context, Error> {
+    // The default shell of the file, else the one of the files it extends
+    let extended = self.read_extends()?;
+    if self.shell.is_none() {
+      self.shell = extended.iter().find_map(|context| context.shell.clone());
+    }
+
     let keys: Vec<String> = self.commands.iter().map(|s| s.0.into()).collect();
 
     for k in keys {
@@ -279,16 +741,39 @@ impl Resolver {
         match value {
           CommandFileDescription::StringCommand(command) => {
             let task_desc = command.as_str().parse::<CommandDescription>()?;
-            self.add_task(key, task_desc);
+            self.add_task(key, task_desc)?;
           }
           CommandFileDescription::Command(task_desc) => {
-            self.add_task(key, task_desc);
+            if task_desc.command.is_empty()
+              && task_desc.script.is_none()
+              && task_desc.exec.is_none()
+            {
+              let err = format!("\"{}\" has no command, script nor exec", key);
+              return Err(Error::Import(err));
+            }
+            self.add_task(key, task_desc)?;
           }
           CommandFileDescription::Concurrent(conc_desc) => {
-            let conc: ConcurrentBuilder = conc_desc.into();
+            let conc = ConcurrentBuilder::try_from(conc_desc).map_err(|e| task_error(&key, e))?;
             self.add_concurrent(key, conc);
           }
-          CommandFileDescription::ExtendedCommand(extd_desc) => {
+          CommandFileDescription::Series(series_desc) => {
+            let series = ConcurrentBuilder::try_from(series_desc).map_err(|e| task_error(&key, e))?;
+            self.add_concurrent(key, series);
+          }
+          CommandFileDescription::List(steps) => {
+            let series = ConcurrentBuilder::try_from(SeriesDescription {
+              series: steps,
+              group: GroupDescription::default(),
+            })?;
+            self.add_concurrent(key, series);
+          }
+          CommandFileDescription::ExtendedCommand(mut extd_desc) => {
+            if let Some(o) = platform_override(&mut extd_desc.overrides) {
+              extd_desc.command = o.command;
+              extd_desc.shell = o.shell.or(extd_desc.shell);
+              extd_desc.environments = merge_primitives(extd_desc.environments, o.environments);
+            }
             self.add_extend(key, extd_desc);
           }
         }
@@ -296,39 +781,52 @@ impl Resolver {
     }
 
     self.resolve_extends()?;
-    self.to_context()
+    self.resolve_matrices();
+    self.resolve_platforms();
+    self.resolve_hooks();
+    self.resolve_shells();
+    self.into_context(extended)
   }
 
-  fn add_task(&mut self, name: String, cmd: CommandDescription) {
+  fn add_task(&mut self, name: String, mut cmd: CommandDescription) -> Result<(), Error> {
+    if let Some(o) = platform_override(&mut cmd.overrides) {
+      cmd.command = o.command.unwrap_or(cmd.command);
+      cmd.shell = o.shell.or(cmd.shell);
+      cmd.environments = merge_primitives(cmd.environments, o.environments);
+    }
+
     if !is_shell_task(&cmd) {
       let extd_desc: ExtendedCommandDescription = cmd.into();
       self.add_extend(name, extd_desc);
+      Ok(())
     } else {
-      self._add_task(name, cmd);
+      self._add_task(name, cmd)
     }
   }
 
-  fn _add_task<I>(&mut self, name: String, task_desc: I)
+  fn _add_task<I>(&mut self, name: String, task_desc: I) -> Result<(), Error>
   where
-    I: Into<CommandBuilder>,
+    I: TryInto<CommandBuilder, Error = Error>,
   {
-    let mut task = task_desc.into();
+    let mut task = task_desc.try_into().map_err(|e| task_error(&name, e))?;
     let vars = task.variables.clone();
     let envs = task.environments.clone();
     task
       .with_name(&name)
       .with_source(&self.source)
-      .with_variables(p_to_s(self.variables.clone())) // Apply file variables
+      .with_variables_from(p_to_s(self.variables.clone()), self.origin()) // Apply file variables
       .with_variables(vars) // Override variables with task
-      .with_environments(p_to_s(self.environments.clone())) // Apply file environments
+      .with_environments_from(p_to_s(self.environments.clone()), self.origin()) // Apply file environments
       .with_environments(envs); // Override environments with task
     self.tasks.insert(name, CommandImported::Command(task));
+    Ok(())
   }
 
   fn add_concurrent(&mut self, name: String, mut conc: ConcurrentBuilder) {
     let vars = conc.variables.clone();
     let envs = conc.environments.clone();
     conc
+      .with_shared_variables(vars.clone()) // Passed to the wk: commands of the task
       .with_name(&name)
       .with_source(&self.source)
       .with_variables(p_to_s(self.variables.clone())) // Apply file variables
@@ -351,12 +849,12 @@ impl Resolver {
       if let Some(cmd) = self.tasks.get(desc.extend.as_str()) {
         if let CommandImported::Command(task) = cmd {
           let extend = ExtendedCommand {
+            name: name.clone(),
             extend: (*task).clone(),
             desc,
           };
 
-          let mut task: CommandBuilder = extend.into();
-          task.with_name(&name);
+          let task = CommandBuilder::try_from(extend).map_err(|e| task_error(&name, e))?;
           self.tasks.insert(name, CommandImported::Command(task));
         } else {
           return Err(Error::Import(format!(
@@ -378,13 +876,101 @@ impl Resolver {
     Ok(())
   }
 
-  fn to_context(mut self) -> Result<Context, Error> {
-    let mut tasks: Dictionary<CommandImported> = HashMap::new();
-    for (key, value) in self.tasks {
-      tasks.insert(key.to_owned(), value);
+  /// Replace each matrix task with its instances, run by a group task of the same name
+  fn resolve_matrices(&mut self) {
+    let names: Vec<String> = self
+      .tasks
+      .iter()
+      .filter(|(_, task)| matches!(task, CommandImported::Command(builder) if builder.has_matrix()))
+      .map(|(name, _)| name.clone())
+      .collect();
+
+    for name in names {
+      let builder = match self.tasks.remove(&name) {
+        Some(CommandImported::Command(builder)) => builder,
+        _ => continue,
+      };
+
+      let mut group = ConcurrentBuilder::new();
+      group
+        .with_name(&name)
+        .with_source(builder.source())
+        .with_sequential(!builder.is_parallel());
+      if let Some(description) = builder.description() {
+        group.with_description(description);
+      }
+      for platforms in builder.platforms().lists() {
+        group.with_platforms(platforms.clone());
+      }
+
+      for combination in builder.matrix_combinations() {
+        let instance = builder.matrix_instance(&combination);
+
+        group.with_task(Dependency::new(instance.name()));
+
+        self.tasks.insert(
+          instance.name().to_string(),
+          CommandImported::Command(instance),
+        );
+      }
+
+      self.tasks.insert(name, CommandImported::Concurrent(group));
     }
+  }
 
-    let mut context = Context { tasks, debug: 0 };
+  /// Restrict every task of the file to the platforms of the file
+  fn resolve_platforms(&mut self) {
+    if self.platforms.is_empty() {
+      return;
+    }
+
+    for task in self.tasks.values_mut() {
+      match task {
+        CommandImported::Command(builder) => {
+          builder.with_platforms(self.platforms.clone());
+        }
+        CommandImported::Concurrent(builder) => {
+          builder.with_platforms(self.platforms.clone());
+        }
+      }
+    }
+  }
+
+  /// Wrap every command task of the file with the hooks of the file
+  fn resolve_hooks(&mut self) {
+    if self.hooks.is_empty() {
+      return;
+    }
+
+    for task in self.tasks.values_mut() {
+      if let CommandImported::Command(builder) = task {
+        builder.wrap_hooks(&self.hooks);
+      }
+    }
+  }
+
+  /// Tasks without a shell use the default shell of the file
+  fn resolve_shells(&mut self) {
+    let shell = match &self.shell {
+      Some(shell) => shell,
+      None => return,
+    };
+
+    for task in self.tasks.values_mut() {
+      match task {
+        CommandImported::Command(builder) if builder.shell().is_none() => {
+          builder.with_shell(shell.clone());
+        }
+        CommandImported::Concurrent(builder) if builder.shell().is_none() => {
+          builder.with_shell(shell.clone());
+        }
+        _ => {}
+      }
+    }
+  }
+
+  fn read_extends(&mut self) -> Result<Vec<Context>, Error> {
+    let mut contexts: Vec<Context> = Vec::new();
 
     if let Some(extends) = self.extends.take() {
       for f in extends {
@@ -393,10 +979,7 @@ impl Resolver {
         let fpath = ff.as_path();
 
         if fpath != self.source {
-          {
-            let c = load(fpath)?;
-            context.extend(c);
-          }
+          contexts.push(read(fpath, true)?);
         } else {
           return Err(Error::Import(
             format!("Cannot extend {:?}", fpath).to_string(),
@@ -405,10 +988,98 @@ impl Resolver {
       }
     }
 
+    Ok(contexts)
+  }
+
+  fn into_context(self, extended: Vec<Context>) -> Result<Context, Error> {
+    let mut tasks: Dictionary<CommandImported> = HashMap::new();
+    for (key, value) in self.tasks {
+      tasks.insert(key.to_owned(), value);
+    }
+
+    // Runs are logged next to the task file
+    let logs = Logs::new(cache_dir(&self.source).join("logs"), self.keep_logs);
+
+    let mut context = Context {
+      tasks,
+      timeout: None,
+      output: None,
+      shell: self.shell,
+      logs,
+      summary: Default::default(),
+    };
+
+    for c in extended {
+      context.extend(c);
+    }
+
     Ok(context)
   }
 }
 
+fn matrix_to_s(matrix: Dictionary<Vec<Primitive>>) -> HashMap<String, Vec<String>> {
+  matrix
+    .into_iter()
+    .map(|(key, values)| (key, values.into_iter().map(|v| v.into()).collect()))
+    .collect()
+}
+
+/// Parse the value of a field, the error tells which field it is
+fn parse_field<T, F>(field: &str, value: Option<Primitive>, parse: F) -> Result<Option<T>, Error>
+where
+  F: FnOnce(&str) -> Result<T, Error>,
+{
+  match value {
+    Some(value) => {
+      let value: String = value.into();
+      parse(&value).map(Some).map_err(|e| field_error(field, e))
+    }
+    None => Ok(None),
+  }
+}
+
+fn field_error(field: &str, error: Error) -> Error {
+  let message = match error {
+    Error::Command(message) | Error::Import(message) => message,
+    error => error.to_string(),
+  };
+  Error::Import(format!("field \"{}\", {}", field, message))
+}
+
+/// Tell which task has the invalid field
+fn task_error(task: &str, error: Error) -> Error {
+  match error {
+    Error::Import(message) => Error::Import(format!("Task \"{}\", {}", task, message)),
+    error => error,
+  }
+}
+
+/// Take the override matching the current platform, the exact platform name wins over `unix`
+fn platform_override(
+  overrides: &mut Option<Dictionary<OverrideDescription>>,
+) -> Option<OverrideDescription> {
+  let mut overrides = overrides.take()?;
+  let key = overrides
+    .keys()
+    .filter(|key| platform::matches(key))
+    .max_by_key(|key| platform::normalize(key) == platform::current())
+    .cloned()?;
+  overrides.remove(&key)
+}
+
+fn merge_primitives(
+  map: Option<Dictionary<Primitive>>,
+  other: Option<Dictionary<Primitive>>,
+) -> Option<Dictionary<Primitive>> {
+  match (map, other) {
+    (Some(mut map), Some(other)) => {
+      map.extend(other);
+      Some(map)
+    }
+    (map, other) => map.or(other),
+  }
+}
+
 fn p_to_s(map: Dictionary<Primitive>) -> Dictionary<String> {
   let mut h: Dictionary<String> = HashMap::new();
   for item in map {
@@ -441,37 +1112,43 @@ fn split_command(cmd: &str) -> Vec<&str> {
   let split: Vec<&str> = cmd.split_whitespace().collect();
   let mut args: Vec<&str> = Vec::new();
 
-  let mut iterator = split.into_iter().enumerate();
-  while let Some((index, arg)) = iterator.next() {
-    if index == 0 {
-      if arg.len() >= 4 && &arg[0..3] == "wk:" {
-        let c = &arg[3..];
-        args.push(c.into());
-        continue;
-      }
+  let iterator = split.into_iter().enumerate();
+  for (index, arg) in iterator {
+    if index == 0 && arg.len() >= 4 && &arg[0..3] == "wk:" {
+      let c = &arg[3..];
+      args.push(c);
+      continue;
     }
-    args.push(arg.into());
+    args.push(arg);
   }
 
   return args;
 }
 
-pub fn load<'a, P>(path: P) -> Result<Context, Error>
+pub fn load<P>(path: P) -> Result<Context, Error>
 where
   P: AsRef<Path>,
 {
-  let path_ref = path.as_ref();
+  read(path.as_ref(), false)
+}
+
+fn read(path_ref: &Path, extended: bool) -> Result<Context, Error> {
   let content = Reader::text(path_ref)?;
   let file: CommandsFile = serde_yaml::from_str(content.as_str())?;
 
   let importer = Resolver {
     source: path_ref.into(),
+    extended,
     tasks: HashMap::new(),
     extended_tasks: Vec::new(),
     extends: file.extends,
+    platforms: file.platforms.unwrap_or_default(),
+    shell: file.shell.and_then(|shell| shell.into_shell()),
+    keep_logs: file.keep_logs.unwrap_or(logs::DEFAULT_KEEP),
+    hooks: file.hooks.into_hooks().unwrap_or_default(),
     commands: file.commands,
-    variables: file.variables.unwrap_or(HashMap::new()),
-    environments: file.environments.unwrap_or(HashMap::new()),
+    variables: file.variables.unwrap_or_default(),
+    environments: file.environments.unwrap_or_default(),
   };
 
   let c = importer.resolve()?;
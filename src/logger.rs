@@ -0,0 +1,57 @@
+use std::{
+  fmt,
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Verbosity of wk's own messages, task outputs are never affected
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub enum Level {
+  Quiet,
+  Normal,
+  Verbose,
+  Debug,
+}
+
+static LEVEL: AtomicUsize = AtomicUsize::new(Level::Normal as usize);
+
+pub fn set_level(level: Level) {
+  LEVEL.store(level as usize, Ordering::SeqCst);
+}
+
+pub fn level() -> Level {
+  match LEVEL.load(Ordering::SeqCst) {
+    0 => Level::Quiet,
+    1 => Level::Normal,
+    2 => Level::Verbose,
+    _ => Level::Debug,
+  }
+}
+
+pub fn enabled(level: Level) -> bool {
+  level <= self::level()
+}
+
+/// Messages go to stderr so that the stdout of tasks can be piped
+pub fn log(level: Level, args: fmt::Arguments) {
+  if enabled(level) {
+    eprintln!("[wk] {}", args);
+  }
+}
+
+macro_rules! info {
+  ($($arg:tt)*) => {
+    $crate::logger::log($crate::logger::Level::Normal, format_args!($($arg)*))
+  };
+}
+
+macro_rules! verbose {
+  ($($arg:tt)*) => {
+    $crate::logger::log($crate::logger::Level::Verbose, format_args!($($arg)*))
+  };
+}
+
+macro_rules! debug {
+  ($($arg:tt)*) => {
+    $crate::logger::log($crate::logger::Level::Debug, format_args!($($arg)*))
+  };
+}
@@ -0,0 +1,227 @@
+use crate::{error::Error, supervisor, utils::path};
+use std::{
+  fs,
+  io::{self, Read, Seek, SeekFrom, Write},
+  path::{Path, PathBuf},
+  sync::{Arc, Mutex},
+  thread,
+  time::{Duration, SystemTime},
+};
+
+/// Number of runs kept when the task file does not tell
+pub const DEFAULT_KEEP: usize = 10;
+
+const FOLLOW_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Written once a run is over, followed logs stop there
+const DONE: &str = ".done";
+/// Labels of the tasks logged in a run, their file names only keep some of their characters
+const TASKS: &str = ".tasks";
+
+/// Outputs of the runs, a directory per run with a file per task
+#[derive(Debug, Clone)]
+pub struct Logs {
+  dir: PathBuf,
+  keep: usize,
+}
+
+impl Logs {
+  pub fn new<P>(dir: P, keep: usize) -> Self
+  where
+    P: Into<PathBuf>,
+  {
+    Logs {
+      dir: dir.into(),
+      keep,
+    }
+  }
+
+  /// Ids of the runs logged, the oldest first
+  pub fn runs(&self) -> Vec<u64> {
+    let mut runs: Vec<u64> = match fs::read_dir(&self.dir) {
+      Ok(entries) => entries
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+        .collect(),
+      Err(_) => Vec::new(),
+    };
+    runs.sort_unstable();
+    runs
+  }
+
+  /// Directory of a new run, the oldest runs beyond the ones kept are removed.
+  /// The new run is kept whatever the number of runs to keep.
+  pub fn start(&self) -> io::Result<RunLog> {
+    fs::create_dir_all(&self.dir)?;
+
+    // Another run may take the same id at the same time
+    let mut id = self.runs().last().map_or(1, |last| last + 1);
+    let dir = loop {
+      let dir = self.dir.join(id.to_string());
+      match fs::create_dir(&dir) {
+        Ok(_) => break dir,
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => id += 1,
+        Err(e) => return Err(e),
+      }
+    };
+
+    let runs = self.runs();
+    let removed = runs.len().saturating_sub(self.keep.max(1));
+    for old in runs.iter().take(removed) {
+      let _ = fs::remove_dir_all(self.dir.join(old.to_string()));
+    }
+
+    Ok(RunLog { id, dir })
+  }
+
+  /// Directory of a run, the last one by default
+  pub fn run(&self, id: Option<u64>) -> Result<(u64, PathBuf), Error> {
+    let runs = self.runs();
+    let id = match id {
+      Some(id) if runs.contains(&id) => id,
+      Some(id) => return Err(Error::Command(format!("Run {} not found in the logs", id))),
+      None => match runs.last() {
+        Some(id) => *id,
+        None => return Err(Error::Command("No run logged yet".to_string())),
+      },
+    };
+    Ok((id, self.dir.join(id.to_string())))
+  }
+}
+
+/// Logs of a run in progress, the run is marked as over once dropped
+#[derive(Debug)]
+pub struct RunLog {
+  id: u64,
+  dir: PathBuf,
+}
+
+impl RunLog {
+  pub fn id(&self) -> u64 {
+    self.id
+  }
+
+  pub fn dir(&self) -> &Path {
+    &self.dir
+  }
+
+  /// Log file of a task, appended to when it runs again
+  pub fn task(&self, label: &str) -> io::Result<TaskLog> {
+    let path = task_path(&self.dir, label);
+    if !path.exists() {
+      let mut tasks = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(self.dir.join(TASKS))?;
+      writeln!(tasks, "{}", label)?;
+    }
+
+    let file = fs::OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(path)?;
+    Ok(TaskLog {
+      file: Arc::new(Mutex::new(file)),
+    })
+  }
+}
+
+impl Drop for RunLog {
+  fn drop(&mut self) {
+    let _ = fs::write(self.dir.join(DONE), "");
+  }
+}
+
+/// Log file of a task, shared by the threads reading its streams
+#[derive(Debug, Clone)]
+pub struct TaskLog {
+  file: Arc<Mutex<fs::File>>,
+}
+
+impl TaskLog {
+  /// Append a line read from the given stream, with the time it was read
+  pub fn write(&self, stream: &str, line: &str) {
+    let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+    let timestamp = humantime::format_rfc3339_millis(SystemTime::now());
+    // The task goes on when its log cannot be written
+    let _ = writeln!(file, "{} {} {}", timestamp, stream, line);
+  }
+}
+
+fn task_path(dir: &Path, label: &str) -> PathBuf {
+  dir.join(format!("{}.log", path::file_name(label)))
+}
+
+/// Tasks logged in a run, in the order of their labels
+pub fn tasks(dir: &Path) -> Vec<String> {
+  let content = fs::read_to_string(dir.join(TASKS)).unwrap_or_default();
+  let mut tasks: Vec<String> = content.lines().map(|label| label.to_string()).collect();
+  tasks.sort();
+  tasks.dedup();
+  tasks
+}
+
+/// Log of a task in a run
+pub fn read(dir: &Path, task: &str) -> Result<String, Error> {
+  match fs::read_to_string(task_path(dir, task)) {
+    Ok(content) => Ok(content),
+    Err(e) if e.kind() == io::ErrorKind::NotFound => {
+      let err = format!("No log for \"{}\" in {}", task, dir.to_string_lossy());
+      Err(Error::Command(err))
+    }
+    Err(e) => Err(e.into()),
+  }
+}
+
+/// Print the logs of a run as they are written, until the run is over.
+/// Every task of the run is followed when none is given, each line prefixed with its name.
+pub fn follow<W: Write>(dir: &Path, task: Option<&str>, out: &mut W) -> Result<(), Error> {
+  let mut offsets: Vec<(String, u64)> = Vec::new();
+
+  loop {
+    // Read once more after the run is over, for the lines written in between
+    let done = dir.join(DONE).exists();
+
+    let names = match task {
+      Some(task) => vec![task.to_string()],
+      None => tasks(dir),
+    };
+    for name in names {
+      if !offsets.iter().any(|(known, _)| *known == name) {
+        offsets.push((name, 0));
+      }
+    }
+
+    for (name, offset) in offsets.iter_mut() {
+      let mut file = match fs::File::open(task_path(dir, name)) {
+        Ok(file) => file,
+        Err(_) => continue,
+      };
+      file.seek(SeekFrom::Start(*offset))?;
+      let mut content = String::new();
+      file.read_to_string(&mut content)?;
+
+      // A line is printed once complete
+      let complete = match content.rfind('\n') {
+        Some(index) => &content[..=index],
+        None => continue,
+      };
+      *offset += complete.len() as u64;
+      for line in complete.lines() {
+        match task {
+          Some(_) => writeln!(out, "{}", line)?,
+          None => writeln!(out, "{} | {}", name, line)?,
+        }
+      }
+      out.flush()?;
+    }
+
+    if done {
+      return Ok(());
+    }
+    if let Some(signal) = supervisor::interrupted() {
+      return Err(Error::Interrupted(signal));
+    }
+    thread::sleep(FOLLOW_INTERVAL);
+  }
+}
@@ -1,37 +1,105 @@
 #![allow(dead_code)]
 
+#[macro_use]
+mod logger;
+
+mod cli;
 mod command;
 mod concurrent;
 mod context;
 mod error;
 mod importer;
+mod logs;
+mod summary;
+mod supervisor;
 mod test;
 mod utils;
 
-use crate::{error::Error, importer::lookup_and_load, utils::argv};
+use crate::{
+  cli::{Action, Cli},
+  command::PlanStep,
+  error::Error,
+  importer::lookup_and_load,
+};
 use futures::executor::block_on;
 
 async fn run() -> Result<(), Error> {
+  let cli = Cli::parse(std::env::args().skip(1))?;
+  logger::set_level(cli.verbosity);
+  supervisor::install(cli.grace_period);
+
   let dir_path = std::env::current_dir()?;
-  let context = lookup_and_load(dir_path.as_path())?;
+  let mut context = lookup_and_load(dir_path.as_path())?;
+  context.timeout = cli.timeout;
+  context.output = cli.output;
+  context.summary = cli.summary;
 
-  let (params, vars) = argv::extract_vars_from_args(std::env::args());
+  match &cli.action {
+    Action::Run(name) if cli.dry_run => {
+      let commands = context.plan(name, Some(&cli.variables))?;
 
-  if params.len() > 0 {
-    context.run(&params[0], Some(&vars)).await?;
-  } else {
-    println!("Task availables");
-    for task in context.tasks.keys() {
-      println!("  {}", task);
+      if cli.json {
+        let plan: Vec<PlanStep> = commands.iter().map(|c| c.into()).collect();
+        println!("{}", serde_json::to_string_pretty(&plan)?);
+      } else {
+        let plan: Vec<String> = commands.iter().map(|c| c.to_string()).collect();
+        print!("{}", plan.join("\n"));
+      }
+    }
+    Action::Run(name) => {
+      context.run(name, Some(&cli.variables)).await?;
+    }
+    Action::Watch(name) => {
+      context.watch(name, Some(&cli.variables)).await?;
+    }
+    Action::Show(name) => {
+      print!("{}", context.inspect(name, Some(&cli.variables))?);
+    }
+    Action::Logs(task) => {
+      let (id, dir) = context.logs.run(cli.run)?;
+
+      if cli.follow {
+        logs::follow(&dir, task.as_deref(), &mut std::io::stdout())?;
+      } else if let Some(task) = task {
+        print!("{}", logs::read(&dir, task)?);
+      } else {
+        println!("Tasks logged in run {}", id);
+        for task in logs::tasks(&dir) {
+          println!("  {}", task);
+        }
+      }
+    }
+    Action::Clean(name) => {
+      let targets = context.clean_targets(name.as_deref(), Some(&cli.variables))?;
+
+      for path in targets {
+        if cli.dry_run {
+          println!("{}", path.to_string_lossy());
+        } else if path.is_dir() {
+          info!("Remove {}", path.to_string_lossy());
+          std::fs::remove_dir_all(&path)?;
+        } else if path.exists() {
+          info!("Remove {}", path.to_string_lossy());
+          std::fs::remove_file(&path)?;
+        }
+      }
+    }
+    Action::List => {
+      println!("Task availables");
+      for task in context.tasks.keys() {
+        if context.is_available(task) {
+          println!("  {}", task);
+        }
+      }
     }
   }
 
   Ok(())
 }
 
-fn main() -> Result<(), Error> {
+fn main() {
   if let Err(e) = block_on(run()) {
-    println!("{:#}", e);
+    eprintln!("{:#}", e);
+    std::process::exit(e.exit_code());
   }
-  Ok(())
 }
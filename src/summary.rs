@@ -0,0 +1,254 @@
+use crate::{command::CommandResult, error::Error};
+use serde::Serialize;
+use std::{fmt, str::FromStr};
+
+/// How the summary of a run is printed
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Format {
+  #[default]
+  Table,
+  Json,
+}
+
+impl FromStr for Format {
+  type Err = Error;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "table" => Ok(Format::Table),
+      "json" => Ok(Format::Json),
+      _ => Err(Error::Command(format!(
+        "Unknown summary \"{}\", expected table or json",
+        s
+      ))),
+    }
+  }
+}
+
+/// What became of a scheduled task
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+  Ok,
+  Failed,
+  /// Its condition was not met, or the run stopped before it
+  Skipped,
+  /// Its outputs were up to date
+  Cached,
+  TimedOut,
+}
+
+impl Status {
+  /// Status and exit code of a task that ran
+  pub fn of(result: &CommandResult) -> (Status, Option<i32>) {
+    match result {
+      Ok(Some(0)) => (Status::Ok, Some(0)),
+      Ok(code) => (Status::Failed, *code),
+      // Stopped by wk, it did not exit on its own
+      Err(Error::Timeout(_, _)) => (Status::TimedOut, None),
+      Err(e @ Error::Failed(_, _))
+      | Err(e @ Error::Script(_, _, _))
+      | Err(e @ Error::Interrupted(_)) => (Status::Failed, Some(e.exit_code())),
+      Err(_) => (Status::Failed, None),
+    }
+  }
+}
+
+impl std::fmt::Display for Status {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      Status::Ok => "ok",
+      Status::Failed => "failed",
+      Status::Skipped => "skipped",
+      Status::Cached => "cached",
+      Status::TimedOut => "timed out",
+    };
+    // Padded by the table
+    f.pad(name)
+  }
+}
+
+/// Outcome of a scheduled task
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskReport {
+  pub task: String,
+  pub status: Status,
+  pub code: Option<i32>,
+  /// Wall-clock seconds, with its retries and hooks
+  pub duration: Option<f64>,
+  /// Tasks of the run it waited for
+  #[serde(skip)]
+  pub after: Vec<usize>,
+  #[serde(skip)]
+  recorded: bool,
+}
+
+/// Every scheduled task of a run, in the order they were scheduled
+#[derive(Debug, Serialize)]
+pub struct Summary {
+  pub tasks: Vec<TaskReport>,
+  /// Longest chain of tasks waiting for each other, it bounds the duration of the run
+  pub critical_path: Vec<String>,
+  /// Seconds spent on the critical path
+  pub critical_duration: f64,
+  /// Seconds the run took
+  pub duration: f64,
+  pub interrupted: bool,
+}
+
+impl Summary {
+  /// The tasks are skipped until they are recorded
+  pub fn new<I>(tasks: I) -> Self
+  where
+    I: IntoIterator<Item = (String, Vec<usize>)>,
+  {
+    let tasks = tasks
+      .into_iter()
+      .map(|(task, after)| TaskReport {
+        task,
+        status: Status::Skipped,
+        code: None,
+        duration: None,
+        after,
+        recorded: false,
+      })
+      .collect();
+
+    Summary {
+      tasks,
+      critical_path: Vec::new(),
+      critical_duration: 0.0,
+      duration: 0.0,
+      interrupted: false,
+    }
+  }
+
+  pub fn record(&mut self, task: &str, status: Status, code: Option<i32>, duration: Option<f64>) {
+    let report = self
+      .tasks
+      .iter_mut()
+      .find(|report| report.task == task && !report.recorded);
+
+    if let Some(report) = report {
+      report.status = status;
+      report.code = code;
+      report.duration = duration;
+      report.recorded = true;
+    }
+  }
+
+  /// Set the duration of the run and find its critical path
+  pub fn finish(&mut self, duration: f64) {
+    self.duration = duration;
+
+    // Longest chain ending with each task, and the task before it in that chain.
+    // Tasks are scheduled after the ones they wait for.
+    let mut chains: Vec<(f64, Option<usize>)> = Vec::new();
+    for report in self.tasks.iter() {
+      let mut chain = (0.0, None);
+      for index in report.after.iter().filter(|index| **index < chains.len()) {
+        if chains[*index].0 > chain.0 {
+          chain = (chains[*index].0, Some(*index));
+        }
+      }
+      chains.push((chain.0 + report.duration.unwrap_or(0.0), chain.1));
+    }
+
+    let mut last: Option<usize> = None;
+    for (index, chain) in chains.iter().enumerate() {
+      let longer = match last {
+        Some(last) => chain.0 > chains[last].0,
+        None => true,
+      };
+      if longer && self.tasks[index].duration.is_some() {
+        last = Some(index);
+      }
+    }
+
+    self.critical_duration = last.map_or(0.0, |last| chains[last].0);
+
+    // Tasks that did not run are not part of it
+    let mut path = Vec::new();
+    while let Some(index) = last {
+      if self.tasks[index].duration.is_some() {
+        path.push(self.tasks[index].task.clone());
+      }
+      last = chains[index].1;
+    }
+    path.reverse();
+    self.critical_path = path;
+  }
+
+  fn count(&self, status: Status) -> usize {
+    self.tasks.iter().filter(|r| r.status == status).count()
+  }
+}
+
+impl std::fmt::Display for Summary {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let width = self
+      .tasks
+      .iter()
+      .map(|report| report.task.chars().count())
+      .fold("Task".len(), usize::max);
+
+    writeln!(
+      f,
+      "{:<width$}  {:<9}  {:>4}  {:>9}",
+      "Task",
+      "Status",
+      "Code",
+      "Duration",
+      width = width
+    )?;
+    for report in self.tasks.iter() {
+      let code = report.code.map_or("-".to_string(), |code| code.to_string());
+      let duration = report
+        .duration
+        .map_or("-".to_string(), |duration| format!("{:.2}s", duration));
+      writeln!(
+        f,
+        "{:<width$}  {:<9}  {:>4}  {:>9}",
+        report.task,
+        report.status,
+        code,
+        duration,
+        width = width
+      )?;
+    }
+
+    if !self.critical_path.is_empty() {
+      writeln!(
+        f,
+        "Critical path: {} ({:.2}s of {:.2}s)",
+        self.critical_path.join(" > "),
+        self.critical_duration,
+        self.duration
+      )?;
+    }
+
+    write!(
+      f,
+      "Summary: {} of {} task(s) done",
+      self.count(Status::Ok),
+      self.tasks.len()
+    )?;
+    for status in [Status::Skipped, Status::Cached].iter() {
+      let count = self.count(*status);
+      if count > 0 {
+        write!(f, ", {} {}", count, status)?;
+      }
+    }
+    let stopped = self
+      .tasks
+      .iter()
+      .find(|report| matches!(report.status, Status::Failed | Status::TimedOut));
+    if let Some(report) = stopped {
+      write!(f, ", \"{}\" {}", report.task, report.status)?;
+    }
+    if self.interrupted {
+      write!(f, ", interrupted")?;
+    }
+    writeln!(f)
+  }
+}
@@ -0,0 +1,251 @@
+use std::{
+  process::{Child, ExitStatus},
+  sync::{
+    atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicUsize, Ordering},
+    Mutex,
+  },
+  thread,
+  time::{Duration, Instant},
+};
+
+// Running tasks, each one leads its own process group.
+// An interruption of wk is forwarded to them
+static RUNNING: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+static SIGNAL: AtomicI32 = AtomicI32::new(0);
+static SIGNAL_COUNT: AtomicUsize = AtomicUsize::new(0);
+// The running tasks are stopped without wk being interrupted, like on a watch restart
+static CANCELLED: AtomicBool = AtomicBool::new(false);
+static GRACE_PERIOD: AtomicU64 = AtomicU64::new(5000);
+
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(5);
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+/// SIGTERM, the signal a cancelled run reports
+const CANCEL_SIGNAL: i32 = 15;
+
+/// Time given to a task to stop after SIGTERM/SIGINT before it gets SIGKILL
+pub fn grace_period() -> Duration {
+  Duration::from_millis(GRACE_PERIOD.load(Ordering::SeqCst))
+}
+
+/// Track a spawned child, its pid is also the id of its process group
+pub fn register(pid: u32) {
+  if let Ok(mut running) = RUNNING.lock() {
+    running.push(pid);
+  }
+}
+
+pub fn unregister(pid: u32) {
+  if let Ok(mut running) = RUNNING.lock() {
+    running.retain(|p| *p != pid);
+  }
+}
+
+/// Whether wk is the foreground process group of its terminal.
+/// A task spawned in another group is handed the terminal to read from it.
+#[cfg(unix)]
+pub fn owns_terminal() -> bool {
+  unsafe { libc::tcgetpgrp(libc::STDIN_FILENO) == libc::getpgrp() }
+}
+
+#[cfg(not(unix))]
+pub fn owns_terminal() -> bool {
+  false
+}
+
+/// Make the process group of the caller the foreground one of the terminal.
+/// Called from the background, SIGTTOU is ignored meanwhile.
+#[cfg(unix)]
+pub fn take_terminal() {
+  unsafe {
+    let previous = libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+    libc::tcsetpgrp(libc::STDIN_FILENO, libc::getpgrp());
+    libc::signal(libc::SIGTTOU, previous);
+  }
+}
+
+/// Take the terminal back from a task that exited. Ctrl-C only reached the task
+/// holding it, wk stops as if it got it too.
+#[cfg(unix)]
+pub fn reclaim_terminal(status: Option<&ExitStatus>) {
+  use std::os::unix::process::ExitStatusExt;
+  take_terminal();
+  if let Some(libc::SIGINT) = status.and_then(|status| status.signal()) {
+    on_signal(libc::SIGINT);
+  }
+}
+
+#[cfg(not(unix))]
+pub fn reclaim_terminal(_status: Option<&ExitStatus>) {}
+
+/// Signal received by wk, if any
+pub fn signal() -> Option<i32> {
+  match SIGNAL.load(Ordering::SeqCst) {
+    0 => None,
+    signal => Some(signal),
+  }
+}
+
+/// Signal the run stops for, the one received by wk or SIGTERM once cancelled
+pub fn interrupted() -> Option<i32> {
+  signal().or_else(|| CANCELLED.load(Ordering::SeqCst).then_some(CANCEL_SIGNAL))
+}
+
+/// Make the current run stop as if interrupted, until `resume`
+pub fn cancel() {
+  CANCELLED.store(true, Ordering::SeqCst);
+}
+
+pub fn resume() {
+  CANCELLED.store(false, Ordering::SeqCst);
+}
+
+/// Send a signal to every running task
+#[cfg(unix)]
+pub fn broadcast(signal: i32) {
+  if let Ok(running) = RUNNING.lock() {
+    for pid in running.iter() {
+      kill(*pid, signal);
+    }
+  }
+}
+
+#[cfg(not(unix))]
+pub fn broadcast(_signal: i32) {}
+
+/// Stop every running task, killed after the grace period, and wait for them to exit
+pub fn stop_all() {
+  let is_empty = || RUNNING.lock().map(|r| r.is_empty()).unwrap_or(true);
+
+  terminate_all(false);
+  let deadline = Instant::now() + grace_period();
+  while !is_empty() && Instant::now() < deadline {
+    thread::sleep(POLL_INTERVAL);
+  }
+
+  terminate_all(true);
+  while !is_empty() {
+    thread::sleep(POLL_INTERVAL);
+  }
+}
+
+#[cfg(unix)]
+fn terminate_all(force: bool) {
+  broadcast(if force { libc::SIGKILL } else { libc::SIGTERM });
+}
+
+/// Without signals, the tree of each task is ended by taskkill
+#[cfg(not(unix))]
+fn terminate_all(force: bool) {
+  let pids: Vec<u32> = match RUNNING.lock() {
+    Ok(running) => running.clone(),
+    Err(_) => return,
+  };
+
+  for pid in pids {
+    taskkill(pid, force);
+  }
+}
+
+#[cfg(not(unix))]
+fn taskkill(pid: u32, force: bool) {
+  let mut taskkill = std::process::Command::new("taskkill");
+  taskkill.args(["/PID", &pid.to_string(), "/T"]);
+  if force {
+    taskkill.arg("/F");
+  }
+  let _ = taskkill
+    .stdout(std::process::Stdio::null())
+    .stderr(std::process::Stdio::null())
+    .status();
+}
+
+/// Signal a task with its whole process group
+#[cfg(unix)]
+pub fn kill(pid: u32, signal: i32) {
+  unsafe {
+    libc::kill(-(pid as i32), signal);
+  }
+}
+
+/// Stop a child and its process group, politely first
+#[cfg(unix)]
+pub fn terminate(child: &mut Child) {
+  let pid = child.id();
+  kill(pid, libc::SIGTERM);
+
+  let deadline = Instant::now() + grace_period();
+  while Instant::now() < deadline {
+    if let Ok(Some(_)) = child.try_wait() {
+      // The leader is gone, make sure nothing is left in its group
+      kill(pid, libc::SIGKILL);
+      return;
+    }
+    thread::sleep(POLL_INTERVAL);
+  }
+
+  kill(pid, libc::SIGKILL);
+  let _ = child.wait();
+}
+
+/// Stop a child and the processes it started
+#[cfg(not(unix))]
+pub fn terminate(child: &mut Child) {
+  taskkill(child.id(), true);
+  let _ = child.kill();
+  let _ = child.wait();
+}
+
+#[cfg(unix)]
+extern "C" fn on_signal(signal: libc::c_int) {
+  SIGNAL.store(signal, Ordering::SeqCst);
+  SIGNAL_COUNT.fetch_add(1, Ordering::SeqCst);
+}
+
+/// Catch SIGINT and SIGTERM, forward them to the running tasks and send
+/// SIGKILL to those still alive after the grace period or on a second signal.
+#[cfg(unix)]
+pub fn install(grace_period: Duration) {
+  GRACE_PERIOD.store(grace_period.as_millis() as u64, Ordering::SeqCst);
+
+  unsafe {
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    libc::signal(libc::SIGINT, handler);
+    libc::signal(libc::SIGTERM, handler);
+  }
+
+  thread::spawn(move || {
+    let mut handled = 0;
+    let mut deadline: Option<Instant> = None;
+
+    loop {
+      thread::sleep(Duration::from_millis(50));
+
+      let count = SIGNAL_COUNT.load(Ordering::SeqCst);
+      if count > handled {
+        handled = count;
+
+        if deadline.is_none() {
+          let signal = SIGNAL.load(Ordering::SeqCst);
+          info!("Interrupted, stopping running tasks");
+          broadcast(signal);
+          deadline = Some(Instant::now() + grace_period);
+        } else {
+          broadcast(libc::SIGKILL);
+        }
+      }
+
+      if let Some(d) = deadline {
+        if Instant::now() >= d {
+          verbose!("Grace period elapsed, killing remaining tasks");
+          broadcast(libc::SIGKILL);
+          deadline = Some(Instant::now() + grace_period);
+        }
+      }
+    }
+  });
+}
+
+#[cfg(not(unix))]
+pub fn install(grace_period: Duration) {
+  GRACE_PERIOD.store(grace_period.as_millis() as u64, Ordering::SeqCst);
+}
@@ -51,4 +51,942 @@ mod tests {
     // println!("{:?}", vars);
     Ok(())
   }
+
+  #[test]
+  fn show_origins() -> Result<(), crate::error::Error> {
+    use crate::command::Origin;
+    let path = std::path::Path::new("./tmp/simple.yml");
+    let context = crate::importer::load(path)?;
+
+    let mut vars = std::collections::HashMap::new();
+    vars.insert("version".to_string(), "4".to_string());
+    let inspection = context.inspect("how", Some(&vars))?;
+    assert_eq!(inspection.order, vec!["hello", "welcome", "how"]);
+
+    let origin = |key: &str| {
+      let var = inspection.variables.iter().find(|v| v.0 == key).unwrap();
+      var.2.clone()
+    };
+    assert_eq!(origin("wk.os"), Origin::Builtin);
+    assert!(inspection.variables.iter().all(|v| v.0 != "os"));
+    assert_eq!(origin("buddy"), Origin::File(path.into()));
+    assert_eq!(origin("sign"), Origin::Task("how".to_string()));
+    assert_eq!(origin("version"), Origin::Cli);
+
+    assert!(context.inspect("unknown", None).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn dry_run_plan() -> Result<(), crate::error::Error> {
+    use crate::{cli::Cli, command::PlanStep};
+    let context = crate::importer::load("./tmp/simple.yml")?;
+
+    let commands = context.plan("welcome", None)?;
+    let plan: Vec<PlanStep> = commands.iter().map(|c| c.into()).collect();
+    let tasks: Vec<&str> = plan.iter().map(|step| step.task.as_str()).collect();
+    assert_eq!(tasks, vec!["hello", "welcome"]);
+    assert_eq!(plan[1].args.last().unwrap(), "echo %Greeting% WK hello 3!");
+    assert_eq!(plan[1].environments["Greeting"], "Welcome to");
+
+    let json = serde_json::to_string(&plan)?;
+    assert!(json.contains("\"task\":\"welcome\""));
+
+    assert!(Cli::parse(vec!["welcome", "-n", "--json"])?.json);
+    assert!(Cli::parse(vec!["welcome", "--json"]).is_err());
+    let cli = Cli::parse(vec!["welcome", "--var.sign=", "--var.buddy=Bob"])?;
+    assert_eq!(cli.variables["sign"], "");
+    assert_eq!(cli.variables["buddy"], "Bob");
+    Ok(())
+  }
+
+  #[test]
+  fn parse_verbosity() -> Result<(), crate::error::Error> {
+    use crate::{cli::Cli, logger::Level};
+    assert_eq!(Cli::parse(vec!["hello"])?.verbosity, Level::Normal);
+    assert_eq!(Cli::parse(vec!["hello", "-v"])?.verbosity, Level::Verbose);
+    assert_eq!(Cli::parse(vec!["-vv", "hello"])?.verbosity, Level::Debug);
+    assert_eq!(Cli::parse(vec!["hello", "--quiet"])?.verbosity, Level::Quiet);
+    assert!(Cli::parse(vec!["hello", "-vvv"]).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn exit_code() -> Result<(), crate::error::Error> {
+    use crate::error::Error;
+    let context = crate::importer::load("./tmp/exit.yml")?;
+
+    let res = futures::executor::block_on(context.run("after", None));
+    match res {
+      Err(Error::Failed(name, code)) => {
+        assert_eq!(name, "fail");
+        assert_eq!(code, 3);
+      }
+      other => panic!("Unexpected result {:?}", other),
+    }
+
+    assert_eq!(Error::Failed("fail".to_string(), 3).exit_code(), 3);
+    assert_eq!(Error::Command("nope".to_string()).exit_code(), 1);
+    Ok(())
+  }
+
+  #[test]
+  fn concurrent() -> Result<(), crate::error::Error> {
+    let context = crate::importer::load("./tmp/concurrent.yml")?;
+
+    let commands = context.plan("echos", None)?;
+    let groups: Vec<Option<&str>> = commands.iter().map(|c| c.group.as_deref()).collect();
+    assert_eq!(groups, vec![None, Some("echos"), Some("echos"), Some("echos")]);
+    assert_eq!(commands[2].argv().last().unwrap(), "echo Hello John");
+    assert_eq!(commands[3].name, "echos#2");
+    // A task run alone can read from the terminal, the ones of a group cannot
+    assert!(commands[0].is_foreground());
+    assert!(!commands[1].is_foreground());
+
+    let results = futures::executor::block_on(context.run("echos", None))?;
+    assert_eq!(results.len(), 4);
+    Ok(())
+  }
+
+  #[test]
+  fn timeout() -> Result<(), crate::error::Error> {
+    use crate::{error::Error, utils::duration};
+    use std::time::Duration;
+    assert_eq!(duration::parse("10m")?, Duration::from_secs(600));
+    assert_eq!(duration::parse("250ms")?, Duration::from_millis(250));
+    assert_eq!(duration::parse("2")?, Duration::from_secs(2));
+    assert!(duration::parse("2 days").is_err());
+    assert!(duration::parse("99999999999999999999h").is_err());
+    assert!(crate::cli::Cli::parse(vec!["hang", "--timeout=99999999999999999999h"]).is_err());
+
+    // An invalid duration names its task and field
+    let err = crate::importer::load("./tmp/invalid/timeout.yml").err().unwrap();
+    let expected = "Task \"hang\", field \"timeout\", Invalid duration \"10x\"";
+    assert_eq!(err.to_string(), format!("[Import] {}", expected));
+    let err = crate::importer::load("./tmp/invalid/delay.yml").err().unwrap();
+    assert!(err.to_string().contains("\"install\", field \"retry.delay\""), "{}", err);
+
+    let context = crate::importer::load("./tmp/timeout.yml")?;
+    let res = futures::executor::block_on(context.run("hang", None));
+    match res {
+      Err(Error::Timeout(name, duration)) => {
+        assert_eq!(name, "hang");
+        assert_eq!(duration, Duration::from_millis(100));
+      }
+      other => panic!("Unexpected result {:?}", other),
+    }
+
+    // The whole process group is stopped, with the commands of a pipeline
+    #[cfg(unix)]
+    {
+      let _ = std::fs::remove_file("./tmp/.out/timeout.pid");
+      let res = futures::executor::block_on(context.run("piped", None));
+      assert!(matches!(res, Err(Error::Timeout(_, _))));
+      let pid = std::fs::read_to_string("./tmp/.out/timeout.pid")?;
+      // Gone, or a zombie left to its new parent
+      let alive = || {
+        let ps = std::process::Command::new("ps")
+          .args(["-o", "stat=", "-p", pid.trim()])
+          .output()
+          .unwrap();
+        let stat = String::from_utf8_lossy(&ps.stdout).trim().to_string();
+        !stat.is_empty() && !stat.starts_with('Z')
+      };
+      let deadline = std::time::Instant::now() + Duration::from_secs(1);
+      while alive() && std::time::Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(20));
+      }
+      assert!(!alive());
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn retry() -> Result<(), crate::error::Error> {
+    use crate::error::Error;
+    use std::time::Duration;
+    let context = crate::importer::load("./tmp/retry.yml")?;
+
+    let commands = context.plan("install", None)?;
+    let retry = commands[0].retry.clone().unwrap();
+    assert_eq!(retry.attempts, 3);
+    assert_eq!(retry.delay(1), Duration::from_millis(10));
+    assert_eq!(retry.delay(3), Duration::from_millis(40));
+    assert!(retry.should_retry(1, &Ok(Some(137))));
+    assert!(!retry.should_retry(1, &Ok(Some(2))));
+    assert!(!retry.should_retry(3, &Ok(Some(1))));
+
+    let res = futures::executor::block_on(context.run("install", None));
+    assert!(matches!(res, Err(Error::Failed(_, 1))));
+    Ok(())
+  }
+
+  #[test]
+  fn platforms() -> Result<(), crate::error::Error> {
+    let context = crate::importer::load("./tmp/platforms.yml")?;
+
+    assert_eq!(context.is_available("home"), cfg!(unix));
+    assert_eq!(context.is_available("home_win"), cfg!(windows));
+    assert!(context.is_available("hello"));
+
+    let (unavailable, expected) = if cfg!(windows) {
+      ("home", "echo Hello windows")
+    } else {
+      ("home_win", "echo Hello unix")
+    };
+    assert!(context.plan(unavailable, None).is_err());
+
+    let commands = context.plan("hello", None)?;
+    assert_eq!(commands[0].argv().last().unwrap(), expected);
+
+    let commands = context.plan("hello_extended", None)?;
+    let expected = expected.replace("Hello", "Extended");
+    assert_eq!(commands[0].argv().last().unwrap(), &expected);
+
+    match context.plan("homes", None) {
+      Err(crate::error::Error::Command(err)) => {
+        assert!(err.starts_with("Cannot run \"homes\""), "{}", err)
+      }
+      _ => panic!("an unavailable dependency must be rejected"),
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn when() -> Result<(), crate::error::Error> {
+    use crate::command::Condition;
+    use futures::executor::block_on;
+
+    let condition: Condition = "!env(WK_UNSET) && (var(target) == chrome || exists(Cargo.toml))"
+      .parse()?;
+    assert_eq!(
+      condition.to_string(),
+      "(!(env(WK_UNSET)) && (var(target) == \"chrome\" || exists(Cargo.toml)))"
+    );
+    assert!("env(CI) &&".parse::<Condition>().is_err());
+    assert!("file(Cargo.toml)".parse::<Condition>().is_err());
+    // A missing value is an error, an empty one is quoted
+    assert!("env(CI) ==".parse::<Condition>().is_err());
+    assert!("var(target) != && true".parse::<Condition>().is_err());
+    assert_eq!("env(CI) == \"\"".parse::<Condition>()?.to_string(), "env(CI) == \"\"");
+
+    let context = crate::importer::load("./tmp/when.yml")?;
+    let commands = context.plan("deploy", None)?;
+    assert!(!commands[0].is_enabled());
+    assert!(commands[1].is_enabled());
+
+    let mut vars = std::collections::HashMap::new();
+    vars.insert("target".to_string(), "firefox".to_string());
+    let commands = context.plan("deploy", Some(&vars))?;
+    assert!(commands[0].is_enabled());
+
+    // Skipped tasks satisfy their dependents
+    let results = block_on(context.run("deploy", None))?;
+    assert_eq!(results.len(), 1);
+
+    // The condition of a group is evaluated once, with the variables of the group
+    let _ = std::fs::remove_file("target/wk-when.count");
+    assert_eq!(block_on(context.run("browsers", None))?.len(), 2);
+    assert_eq!(std::fs::read_to_string("target/wk-when.count")?, "evaluated\n");
+    vars.insert("browser".to_string(), "firefox".to_string());
+    assert_eq!(block_on(context.run("browsers", Some(&vars)))?.len(), 0);
+    Ok(())
+  }
+
+  #[test]
+  fn fingerprint() -> Result<(), crate::error::Error> {
+    use crate::command::Fingerprint;
+    use crate::utils::{glob, path};
+    use futures::executor::block_on;
+
+    assert!(glob::matcher("src/**/*.rs")?.is_match("src/main.rs"));
+    assert!(glob::matcher("src/**/*.rs")?.is_match("src/utils/glob.rs"));
+    assert!(!glob::matcher("src/*.rs")?.is_match("src/utils/glob.rs"));
+    assert!(glob::matcher("tmp/{simple,clean}.yml")?.is_match("tmp/simple.yml"));
+    assert!(glob::matcher("src/[ab").is_err());
+    assert_eq!(
+      glob::expand(std::path::Path::new("."), "src/**/glob.rs")?,
+      vec![std::path::PathBuf::from("./src/utils/glob.rs")]
+    );
+
+    // A symlink to a parent directory is not followed
+    #[cfg(unix)]
+    {
+      let dir = std::path::Path::new("target/wk-glob");
+      std::fs::create_dir_all(dir)?;
+      std::fs::write(dir.join("a.txt"), "")?;
+      if std::fs::symlink_metadata(dir.join("loop")).is_err() {
+        std::os::unix::fs::symlink("..", dir.join("loop"))?;
+      }
+      assert_eq!(glob::expand(dir, "**/*.txt")?, vec![dir.join("a.txt")]);
+    }
+
+    let context = crate::importer::load("./tmp/fingerprint.yml")?;
+    let mut vars = std::collections::HashMap::new();
+    vars.insert("message".to_string(), "again".to_string());
+    let command = context.plan("stamp", None)?.remove(0);
+    let again = context.plan("stamp", Some(&vars))?.remove(0);
+    let _ = std::fs::remove_file(Fingerprint::path(&command));
+    let _ = std::fs::remove_file(Fingerprint::path(&again));
+    let _ = std::fs::remove_file("target/wk-stamp.txt");
+
+    assert_eq!(block_on(context.run("stamp", None))?.len(), 1);
+    assert_eq!(block_on(context.run("stamp", None))?.len(), 0);
+
+    // A different variable changes the fingerprint
+    assert_eq!(block_on(context.run("stamp", Some(&vars)))?.len(), 1);
+
+    // Each invocation has its own file, labels mapped alike to file names too
+    assert_ne!(Fingerprint::path(&command), Fingerprint::path(&again));
+    assert_ne!(path::file_name("a:b"), path::file_name("a_b"));
+    assert!(path::file_name("a:b").starts_with("a_b-"));
+
+    // Missing outputs are rebuilt
+    std::fs::remove_file("target/wk-stamp.txt")?;
+    assert_eq!(block_on(context.run("stamp", Some(&vars)))?.len(), 1);
+    Ok(())
+  }
+
+  #[test]
+  fn clean() -> Result<(), crate::error::Error> {
+    std::fs::create_dir_all("tmp/.out/clean")?;
+    std::fs::create_dir_all("tmp/.out/dist")?;
+    std::fs::write("tmp/.out/clean/a.txt", "")?;
+    std::fs::write("tmp/.out/clean/b.txt", "")?;
+
+    let cwd = std::env::current_dir()?;
+    let context = crate::importer::load("./tmp/clean.yml")?;
+    let targets = context.clean_targets(Some("build"), None)?;
+    assert_eq!(
+      targets,
+      vec![cwd.join("tmp/.out/clean/a.txt"), cwd.join("tmp/.out/clean/b.txt")]
+    );
+
+    // A directory output is removed as a whole
+    let targets = context.clean_targets(Some("dist"), None)?;
+    assert_eq!(targets, vec![cwd.join("tmp/.out/dist")]);
+
+    // Nothing outside of the directory of the task file
+    assert!(context.clean_targets(Some("escape"), None).is_err());
+    assert!(context.clean_targets(Some("root"), None).is_err());
+    assert!(context.clean_targets(Some("empty"), None).is_err());
+    assert!(context.clean_targets(None, None).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn watch() -> Result<(), crate::error::Error> {
+    use crate::utils::glob;
+    use std::path::Path;
+
+    let base = Path::new("/project");
+    assert!(glob::contains(base, "src/**/*.rs", Path::new("/project/src/a/b.rs")));
+    assert!(!glob::contains(base, "src/**/*.rs", Path::new("/project/src/a/b.md")));
+    assert!(glob::contains(base, "src", Path::new("/project/src/a/b.md")));
+    assert!(!glob::contains(base, "src", Path::new("/project/tests/a.rs")));
+
+    let context = crate::importer::load("./tmp/watch.yml")?;
+    assert_eq!(
+      context.watch_patterns("serve", None)?,
+      vec!["src/**/*.rs".to_string(), "tmp/*.yml".to_string()]
+    );
+
+    // A change runs again the task watching it and its dependents only
+    let cwd = std::env::current_dir()?;
+    let names = |changed: &str| -> Result<Vec<String>, crate::error::Error> {
+      let plan = context.plan("serve", None)?;
+      let commands = context.affected(plan, &cwd, &[cwd.join(changed)]);
+      Ok(commands.into_iter().map(|c| c.name).collect())
+    };
+    assert_eq!(names("tmp/watch.yml")?, vec!["serve"]);
+    assert_eq!(names("src/main.rs")?, vec!["compile", "serve"]);
+    Ok(())
+  }
+
+  #[test]
+  fn service() -> Result<(), crate::error::Error> {
+    use crate::error::Error;
+    use futures::executor::block_on;
+    use std::time::{Duration, Instant};
+
+    // An invalid pattern names its task and field
+    let err = crate::importer::load("./tmp/invalid/ready.yml").err().unwrap().to_string();
+    assert!(err.starts_with("[Import] Task \"api\", field \"ready.stdout\""), "{}", err);
+    assert!(err.contains("listening on ("), "{}", err);
+
+    let _ = std::fs::remove_file("tmp/.out/db.ready");
+    let context = crate::importer::load("./tmp/service.yml")?;
+
+    // Services are stopped once the run ends
+    let start = Instant::now();
+    assert_eq!(block_on(context.run("test", None))?.len(), 3);
+    assert!(start.elapsed() < Duration::from_secs(10));
+
+    // A service exiting before being ready fails the run
+    assert!(block_on(context.run("crash", None)).is_err());
+
+    // So does a service exiting while its dependents run
+    match block_on(context.run("unstable", None)) {
+      Err(Error::Command(err)) => assert!(err.contains("after being ready"), "{}", err),
+      _ => panic!("a service exiting while in use must fail the run"),
+    }
+
+    // The hooks of a service wrap its start
+    block_on(context.run("hooks", None))?;
+    let _ = std::fs::remove_file("tmp/.out/hooked.ready");
+    let _ = std::fs::remove_file("tmp/.out/hooked.after");
+    Ok(())
+  }
+
+  #[test]
+  fn series() -> Result<(), crate::error::Error> {
+    let context = crate::importer::load("./tmp/series.yml")?;
+
+    let commands = context.plan("steps", None)?;
+    let series: Vec<Option<&str>> = commands.iter().map(|c| c.series.as_deref()).collect();
+    assert_eq!(series, vec![Some("steps"); 3]);
+    assert!(commands.iter().all(|c| c.group.is_none()));
+    assert_eq!(commands[0].argv().last().unwrap(), "echo $GREETING Marc");
+    assert_eq!(commands[1].argv().last().unwrap(), "echo $GREETING John");
+    assert_eq!(commands[2].argv().last().unwrap(), "echo Marc!");
+    assert_eq!(commands[2].environments.get("GREETING").unwrap(), "Hi");
+
+    let commands = context.plan("shorthand", None)?;
+    assert_eq!(commands.len(), 2);
+    assert_eq!(commands[0].argv().last().unwrap(), "echo $GREETING World");
+
+    let results = futures::executor::block_on(context.run("steps", None))?;
+    assert_eq!(results.len(), 3);
+    Ok(())
+  }
+
+  #[test]
+  fn matrix() -> Result<(), crate::error::Error> {
+    use futures::executor::block_on;
+    use std::collections::HashMap;
+
+    let context = crate::importer::load("./tmp/matrix.yml")?;
+
+    let commands = context.plan("build", None)?;
+    let names: Vec<&str> = commands.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(
+      names,
+      vec![
+        "build[environment=experience,target=chrome]",
+        "build[environment=experience,target=firefox]",
+        "build[environment=tool,target=chrome]",
+        "build[environment=tool,target=firefox]",
+      ]
+    );
+    assert!(commands.iter().all(|c| c.series.as_deref() == Some("build")));
+
+    // Given variables select the instances
+    let mut vars = HashMap::new();
+    vars.insert("target".to_string(), "firefox".to_string());
+    let commands = context.plan("build", Some(&vars))?;
+    assert_eq!(commands.len(), 2);
+    assert_eq!(commands[1].argv().last().unwrap(), "echo build tool for firefox");
+    let commands = context.plan("firefox", None)?;
+    let names: Vec<&str> = commands.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(
+      names,
+      vec![
+        "build[environment=experience,target=firefox]",
+        "build[environment=tool,target=firefox]",
+        "firefox",
+      ]
+    );
+    vars.insert("environment".to_string(), "other".to_string());
+    assert!(context.plan("build", Some(&vars)).is_err());
+
+    // An instance is addressable on its own, matrix variables are kept over the given ones
+    let commands = context.plan("build[environment=tool,target=chrome]", Some(&vars))?;
+    assert_eq!(commands.len(), 1);
+    assert_eq!(commands[0].argv().last().unwrap(), "echo build tool for chrome");
+
+    // Values are given as they are, spaces and variables included
+    let commands = context.plan("greet", None)?;
+    let argv: Vec<String> = commands.iter().map(|c| c.argv().pop().unwrap()).collect();
+    assert_eq!(argv, vec!["echo \"hello world\"", "echo \"${HOME}\""]);
+    assert_eq!(commands[0].name, "greet[message=hello world]");
+
+    let commands = context.plan("zip", None)?;
+    assert!(commands.iter().all(|c| c.group.as_deref() == Some("zip")));
+    assert_eq!(block_on(context.run("zip", None))?.len(), 2);
+    Ok(())
+  }
+
+  #[test]
+  fn depends() -> Result<(), crate::error::Error> {
+    let context = crate::importer::load("./tmp/depends.yml")?;
+
+    let commands = context.plan("release", None)?;
+    let labels: Vec<String> = commands.iter().map(|c| c.label()).collect();
+    assert_eq!(
+      labels,
+      vec![
+        "lint",
+        "build --var.target=firefox",
+        "build --var.target=safari",
+        "build",
+        "release"
+      ]
+    );
+    assert_eq!(commands[1].argv().last().unwrap(), "echo build for firefox");
+    assert_eq!(commands[2].argv().last().unwrap(), "echo build for safari");
+    assert_eq!(commands[3].argv().last().unwrap(), "echo build for chrome");
+
+    let results = futures::executor::block_on(context.run("release", None))?;
+    assert_eq!(results.len(), 5);
+
+    // A cycle is reported with the tasks it goes through
+    match context.plan("ping", None) {
+      Err(crate::error::Error::Command(err)) => {
+        assert_eq!(err, "Dependency cycle: ping > pong > ping")
+      }
+      _ => panic!("a dependency cycle must be rejected"),
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn dedup() -> Result<(), crate::error::Error> {
+    let context = crate::importer::load("./tmp/dedup.yml")?;
+
+    // Identical invocations run once, whatever their spelling
+    let commands = context.plan("all", None)?;
+    let argv: Vec<String> = commands
+      .iter()
+      .map(|c| c.argv().last().unwrap().clone())
+      .collect();
+    assert_eq!(
+      argv,
+      vec!["echo hello?", "echo hello!", "echo hello.", "echo all"]
+    );
+    assert_eq!(commands[0].label(), "echo --var.sign=?");
+    assert_eq!(commands[2].label(), "echo");
+
+    let step: crate::command::PlanStep = (&commands[1]).into();
+    assert_eq!(step.arguments, vec!["--var.sign=!"]);
+    assert_eq!(step.variables.get("sign").unwrap(), "!");
+
+    // So are the invocations of a group, each one labelling its entries
+    let commands = context.plan("both", None)?;
+    let labels: Vec<String> = commands.iter().map(|c| c.label()).collect();
+    assert_eq!(
+      labels,
+      vec![
+        "pair#0 --var.sign=?",
+        "echo --var.sign=?",
+        "pair#0 --var.sign=!",
+        "echo --var.sign=!",
+        "both"
+      ]
+    );
+    assert_eq!(commands[3].argv().last().unwrap(), "echo hello!");
+    assert_eq!(commands[2].group_label().unwrap(), "pair --var.sign=!");
+    assert!(commands[2].to_string().contains("Concurrently with: pair --var.sign=!"));
+    Ok(())
+  }
+
+  #[test]
+  fn hooks() -> Result<(), crate::error::Error> {
+    use futures::executor::block_on;
+    use std::fs;
+
+    let context = crate::importer::load("./tmp/hooks.yml")?;
+    let log = "./tmp/.out/hooks.log";
+    let _ = fs::remove_file(log);
+
+    block_on(context.run("build", None))?;
+    assert_eq!(
+      fs::read_to_string(log)?,
+      "file-before\nbefore\nbuild\nnotify-ok\nfinally\nfile-finally\n"
+    );
+
+    fs::remove_file(log)?;
+    match block_on(context.run("broken", None)) {
+      Err(crate::error::Error::Failed(name, 3)) => assert_eq!(name, "broken"),
+      other => panic!("Unexpected result {:?}", other.map(|_| ())),
+    }
+    assert_eq!(
+      fs::read_to_string(log)?,
+      "file-before\nnotify-failed\nfinally\nfile-finally\n"
+    );
+
+    fs::remove_file(log)?;
+    Ok(())
+  }
+
+  #[test]
+  fn script() -> Result<(), crate::error::Error> {
+    use futures::executor::block_on;
+    use std::fs;
+
+    let context = crate::importer::load("./tmp/script.yml")?;
+
+    let command = context.create_command("python", None).unwrap();
+    assert_eq!(command.argv()[..2], ["python3", "-u"]);
+
+    let commands = context.plan("multi", None)?;
+    assert_eq!(commands[0].argv()[0], "bash");
+    block_on(context.run("multi", None))?;
+    assert_eq!(
+      fs::read_to_string("./tmp/.out/script.log")?,
+      "Hello World\ndone\n"
+    );
+    fs::remove_file("./tmp/.out/script.log")?;
+
+    // Hook steps run instead of the script, not along with it
+    block_on(context.run("hooked", None))?;
+    assert_eq!(fs::read_to_string("./tmp/.out/script.log")?, "script\nafter\n");
+    fs::remove_file("./tmp/.out/script.log")?;
+
+    // The failing line is reported and the script file removed
+    match block_on(context.run("broken", None)) {
+      Err(crate::error::Error::Script(name, 1, line)) => {
+        assert_eq!(name, "broken");
+        assert_eq!(line, "line 2: false");
+      }
+      other => panic!("Unexpected result {:?}", other.map(|_| ())),
+    }
+    // Unless another exit followed the failure
+    match block_on(context.run("recovered", None)) {
+      Err(crate::error::Error::Failed(name, 3)) => assert_eq!(name, "recovered"),
+      other => panic!("Unexpected result {:?}", other.map(|_| ())),
+    }
+    // Script files are kept in a directory private to the user
+    #[cfg(unix)]
+    let dir = {
+      use std::os::unix::fs::PermissionsExt;
+      let dir = std::env::temp_dir().join(format!("wk-{}", unsafe { libc::getuid() }));
+      assert_eq!(fs::metadata(&dir)?.permissions().mode() & 0o777, 0o700);
+      dir
+    };
+    #[cfg(not(unix))]
+    let dir = std::env::temp_dir().join("wk");
+
+    let prefix = format!("{}-", std::process::id());
+    let left = fs::read_dir(dir)?
+      .flatten()
+      .map(|entry| entry.file_name().to_string_lossy().to_string())
+      .any(|name| name.starts_with(&prefix) && name.contains("-broken"));
+    assert!(!left);
+    Ok(())
+  }
+
+  #[test]
+  fn exec() -> Result<(), crate::error::Error> {
+    use futures::executor::block_on;
+
+    let context = crate::importer::load("./tmp/exec.yml")?;
+
+    let command = context.create_command("direct", None).unwrap();
+    assert_eq!(command.argv(), vec!["printf", "%s|", "a  b", "c d", "e f"]);
+
+    // No shell expansion, arguments are appended after the program ones
+    let command = context.create_command("extra", None).unwrap();
+    assert_eq!(
+      command.argv(),
+      vec!["printf", "%s|", "hello world", "$HOME", "last"]
+    );
+    assert_eq!(block_on(context.run("extra", None))?.len(), 1);
+
+    // An extended task without shell splits the command line it was given
+    let command = context.create_command("unquoted", None).unwrap();
+    assert_eq!(command.argv(), vec!["printf", "%s|", "a  b", "c  d"]);
+
+    // Hook steps run with the shell, instead of the exec of the task
+    block_on(context.run("hooked", None))?;
+    assert_eq!(std::fs::read_to_string("./tmp/.out/exec.log")?, "after");
+    std::fs::remove_file("./tmp/.out/exec.log")?;
+
+    match block_on(context.run("missing", None)) {
+      Err(crate::error::Error::Command(err)) => {
+        assert_eq!(err, "Program \"wk-missing-program\" not found in PATH")
+      }
+      other => panic!("Unexpected result {:?}", other.map(|_| ())),
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn shell() -> Result<(), crate::error::Error> {
+    use futures::executor::block_on;
+    use std::fs;
+
+    let context = crate::importer::load("./tmp/shell.yml")?;
+
+    let command = context.create_command("default", None).unwrap();
+    assert_eq!(command.argv()[..2], ["sh", "-c"]);
+    block_on(context.run("default", None))?;
+    assert_eq!(fs::read_to_string("./tmp/.out/shell.log")?, "sh\n");
+    fs::remove_file("./tmp/.out/shell.log")?;
+
+    let command = context.create_command("custom", None).unwrap();
+    assert_eq!(
+      command.argv(),
+      vec!["bash", "-e", "-u", "-c", "echo custom"]
+    );
+
+    let command = context.create_command("pwsh", None).unwrap();
+    assert_eq!(
+      command.argv(),
+      vec!["pwsh", "-NoProfile", "-Command", "Write-Output hi"]
+    );
+
+    // Commands of a group use its shell, a task keeps its own
+    let commands = context.plan("group", None)?;
+    let argv: Vec<Vec<String>> = commands.iter().map(|c| c.argv()).collect();
+    assert!(argv.contains(&vec!["sh".into(), "-c".into(), "echo grouped".into()]));
+    assert!(argv.contains(&vec![
+      "bash".into(),
+      "-e".into(),
+      "-u".into(),
+      "-c".into(),
+      "echo custom".into()
+    ]));
+
+    // An extended file keeps its shell
+    let command = context.create_command("base", None).unwrap();
+    assert_eq!(command.argv()[..3], ["bash", "-o", "pipefail"]);
+
+    // A file without shell inherits the one of the file it extends
+    let context = crate::importer::load("./tmp/shell_inherit.yml")?;
+    let command = context.create_command("inherited", None).unwrap();
+    assert_eq!(command.argv()[..3], ["bash", "-o", "pipefail"]);
+    assert_eq!(block_on(context.run("inherited", None))?.len(), 1);
+
+    // Tasks use bash whatever the shell of the user, unless their file opts in with $SHELL
+    #[cfg(unix)]
+    {
+      std::env::set_var("SHELL", "/bin/sh");
+      let context = crate::importer::load("./tmp/simple.yml")?;
+      let command = context.create_command("echo", None).unwrap();
+      assert_eq!(command.argv()[..2], ["bash", "-c"]);
+
+      let context = crate::importer::load("./tmp/shell_user.yml")?;
+      let command = context.create_command("user", None).unwrap();
+      assert_eq!(command.argv()[..2], ["/bin/sh", "-c"]);
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn output() -> Result<(), crate::error::Error> {
+    use crate::{
+      cli::Cli,
+      command::{prefix, Output},
+    };
+    use futures::executor::block_on;
+    use std::fs;
+
+    let cli = Cli::parse(vec!["hello", "--output=grouped"])?;
+    assert_eq!(cli.output, Some(Output::Grouped));
+    assert!(Cli::parse(vec!["hello", "--output=loud"]).is_err());
+
+    assert_eq!(prefix("build", false), "build | ");
+    assert_eq!(prefix("build", true), prefix("build", true));
+    assert!(prefix("build", true).starts_with("\x1b["));
+
+    let mut context = crate::importer::load("./tmp/output.yml")?;
+    let outputs = |context: &crate::context::Context, name: &str| -> Vec<Output> {
+      let commands = context.plan(name, None).unwrap();
+      commands.iter().map(|c| c.output_mode()).collect()
+    };
+
+    // Commands running along others are prefixed unless they set their own output
+    assert_eq!(outputs(&context, "hello"), vec![Output::Inherit]);
+    assert_eq!(
+      outputs(&context, "all"),
+      vec![Output::Prefixed, Output::Silent, Output::Prefixed]
+    );
+    assert_eq!(
+      outputs(&context, "blocks"),
+      vec![Output::Grouped, Output::Grouped]
+    );
+    assert_eq!(block_on(context.run("blocks", None))?.len(), 2);
+
+    block_on(context.run("file", None))?;
+    assert_eq!(fs::read_to_string("./tmp/.out/output.log")?, "written\n");
+    fs::remove_file("./tmp/.out/output.log")?;
+
+    // The output of the run comes after the ones of the tasks and groups
+    context.output = Some(Output::Grouped);
+    assert_eq!(outputs(&context, "hello"), vec![Output::Grouped]);
+    assert_eq!(
+      outputs(&context, "all"),
+      vec![Output::Grouped, Output::Silent, Output::Grouped]
+    );
+    assert_eq!(block_on(context.run("all", None))?.len(), 3);
+    Ok(())
+  }
+
+  #[test]
+  fn export() -> Result<(), crate::error::Error> {
+    use futures::executor::block_on;
+    use std::{collections::HashMap, fs};
+
+    let context = crate::importer::load("./tmp/export.yml")?;
+    let log = || -> Result<String, crate::error::Error> {
+      let content = fs::read_to_string("./tmp/.out/export.log")?;
+      fs::remove_file("./tmp/.out/export.log")?;
+      Ok(content)
+    };
+
+    // Commands are planned before the variables are exported
+    let commands = context.plan("release", None)?;
+    assert!(commands[2].argv().last().unwrap().contains("${version}"));
+
+    // Trimmed stdout and KEY=VALUE lines, as variables and environments
+    assert_eq!(block_on(context.run("release", None))?.len(), 3);
+    assert_eq!(log()?, "1.2.3 abc123 1.2.3 main\n");
+
+    // Exported variables come over the ones of the task, given ones over both
+    block_on(context.run("pinned", None))?;
+    assert_eq!(log()?, "1.2.3\n");
+    let mut vars = HashMap::new();
+    vars.insert("version".to_string(), "cli".to_string());
+    block_on(context.run("pinned", Some(&vars)))?;
+    assert_eq!(log()?, "cli\n");
+
+    // Only the tasks depending on the exporter get its variables, whatever the run order
+    block_on(context.run("after", None))?;
+    assert_eq!(log()?, "dev\n");
+
+    // Steps of a series get the variables of the previous ones
+    block_on(context.run("steps", None))?;
+    assert_eq!(log()?, "1.2.3\n");
+
+    // Each invocation exports its own variables, only to the tasks depending on it directly
+    let _ = fs::remove_file("./tmp/.out/export.log");
+    block_on(context.run("both", None))?;
+    assert_eq!(log()?, "v-1\nv-2\n");
+    block_on(context.run("above", None))?;
+    assert_eq!(log()?, "v-1\nnone\n");
+
+    match block_on(context.run("missing", None)) {
+      Err(crate::error::Error::Command(err)) => {
+        assert_eq!(err, "\"missing\" printed no \"TAG=\" line to export")
+      }
+      other => panic!("Unexpected result {:?}", other.map(|_| ())),
+    }
+    Ok(())
+  }
+
+  #[test]
+  fn logs() -> Result<(), crate::error::Error> {
+    use crate::{
+      cli::{Action, Cli},
+      logs,
+    };
+    use futures::executor::block_on;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let leap = UNIX_EPOCH + Duration::from_millis(951_782_400_250);
+    let timestamp = humantime::format_rfc3339_millis(leap).to_string();
+    assert_eq!(timestamp, "2000-02-29T00:00:00.250Z");
+
+    let cli = Cli::parse(vec!["logs", "a", "--run=2", "-f"])?;
+    assert_eq!(cli.action, Action::Logs(Some("a".to_string())));
+    assert_eq!((cli.run, cli.follow), (Some(2), true));
+    assert!(Cli::parse(vec!["logs", "--run=last"]).is_err());
+
+    // Values are given after an equal sign or as the next argument
+    let cli = Cli::parse(vec!["logs", "--run", "3", "a"])?;
+    assert_eq!(cli.action, Action::Logs(Some("a".to_string())));
+    assert_eq!(cli.run, Some(3));
+    let cli = Cli::parse(vec![
+      "--timeout", "1s", "--grace-period", "2s", "--output", "silent", "--summary", "json", "b",
+      "--var.buddy", "Bob",
+    ])?;
+    assert_eq!(cli.action, Action::Run("b".to_string()));
+    assert_eq!(cli.timeout, Some(Duration::from_secs(1)));
+    assert_eq!(cli.grace_period, Duration::from_secs(2));
+    assert_eq!(cli.output, Some(crate::command::Output::Silent));
+    assert_eq!(cli.summary, crate::summary::Format::Json);
+    assert_eq!(cli.variables.get("buddy").unwrap(), "Bob");
+    assert!(Cli::parse(vec!["logs", "--run"]).is_err());
+
+    let _ = std::fs::remove_dir_all("./tmp/logs/.wk");
+
+    // Every run is logged, keep_logs only tells how many runs are kept, the last one at least
+    let none = logs::Logs::new("./tmp/logs/.wk/none", 0);
+    drop(none.start()?);
+    drop(none.start()?);
+    assert_eq!(none.runs(), vec![2]);
+
+    let context = crate::importer::load("./tmp/logs/logs.yml")?;
+    let logs = &context.logs;
+
+    // Each task of the group gets its own log, stdout and stderr lines are timestamped
+    assert!(block_on(context.run("both", None)).is_err());
+    let (id, dir) = logs.run(None)?;
+    assert_eq!(id, 1);
+    assert_eq!(logs::tasks(&dir), vec!["a", "b"]);
+    let mut lines: Vec<String> = logs::read(&dir, "a")?
+      .lines()
+      .map(|line| line[25..].to_string())
+      .collect();
+    // stdout and stderr are read apart, their lines may be logged in any order
+    lines.sort();
+    assert_eq!(lines, vec!["err a-err", "out a-out"]);
+    assert!(logs::read(&dir, "missing").is_err());
+
+    // A run over is followed until its end, lines prefixed by their task
+    let mut out: Vec<u8> = Vec::new();
+    logs::follow(&dir, None, &mut out)?;
+    let out = String::from_utf8_lossy(&out);
+    assert!(out.lines().any(|line| line.starts_with("b | ") && line.ends_with("out b-out")));
+
+    // Only the last runs are kept
+    block_on(context.run("a", None))?;
+    block_on(context.run("a", None))?;
+    assert_eq!(logs.runs(), vec![2, 3]);
+    assert!(logs.run(Some(1)).is_err());
+    Ok(())
+  }
+
+  #[test]
+  fn summary() -> Result<(), crate::error::Error> {
+    use crate::{
+      cli::Cli,
+      error::Error,
+      summary::{Format, Status},
+    };
+    use futures::executor::block_on;
+    use std::time::Duration;
+
+    assert_eq!(Cli::parse(vec!["ci"])?.summary, Format::Table);
+    assert_eq!(Cli::parse(vec!["ci", "--summary=table"])?.summary, Format::Table);
+    assert_eq!(Cli::parse(vec!["ci", "--summary=json"])?.summary, Format::Json);
+    assert!(Cli::parse(vec!["ci", "--summary=xml"]).is_err());
+
+    assert_eq!(Status::of(&Ok(Some(0))), (Status::Ok, Some(0)));
+    assert_eq!(Status::of(&Ok(Some(2))), (Status::Failed, Some(2)));
+    let timeout = Error::Timeout("a".to_string(), Duration::from_secs(1));
+    assert_eq!(Status::of(&Err(timeout)), (Status::TimedOut, None));
+
+    let context = crate::importer::load("./tmp/summary.yml")?;
+    assert_eq!(block_on(context.run("ci", None))?.len(), 4);
+
+    // The longest chain of tasks waiting for each other, tasks that did not run left out
+    let commands = context.plan("ci", None)?;
+    let mut summary = context.summarize(&commands);
+    summary.record("lint", Status::Ok, Some(0), Some(0.5));
+    summary.record("compile", Status::Cached, None, None);
+    summary.record("test", Status::Ok, Some(0), Some(0.25));
+    summary.record("ci", Status::Failed, Some(1), Some(0.5));
+    summary.finish(1.5);
+    assert_eq!(summary.critical_path, vec!["lint", "ci"]);
+    assert_eq!(summary.critical_duration, 1.0);
+    assert_eq!(summary.tasks[3].status, Status::Skipped);
+
+    let table = summary.to_string();
+    assert!(table.contains("compile  cached        -          -"));
+    assert!(table.contains("Critical path: lint > ci (1.00s of 1.50s)"));
+    let footer = "Summary: 2 of 5 task(s) done, 1 skipped, 1 cached, \"ci\" failed\n";
+    assert!(table.ends_with(footer));
+    Ok(())
+  }
 }
@@ -1,3 +1,6 @@
+#![allow(clippy::needless_return, clippy::needless_borrow, clippy::int_plus_one)]
+#![allow(clippy::redundant_static_lifetimes)]
+
 use std::collections::HashMap;
 
 const MATCHES: [&'static str; 5] = ["--", "-", "--var.", "=", "true"];
@@ -201,4 +204,76 @@ pub fn extract_option_from_args(argv: std::env::Args) -> (Vec<String>, HashMap<S
   }
 
   (params_string, options_string)
-}
\ No newline at end of file
+}
+
+/// Quote an argument the way a POSIX shell would need it
+pub fn quote(s: &str) -> String {
+  let is_safe = |c: char| c.is_ascii_alphanumeric() || "-_./:=@%+,".contains(c);
+
+  if !s.is_empty() && s.chars().all(is_safe) {
+    return s.to_string();
+  }
+
+  format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// Split a command line into arguments the way a POSIX shell would,
+/// honoring single quotes, double quotes and backslashes, without any expansion
+pub fn split(s: &str) -> Vec<String> {
+  let mut args: Vec<String> = Vec::new();
+  let mut current = String::new();
+  let mut in_word = false;
+  let mut chars = s.chars();
+
+  while let Some(c) = chars.next() {
+    match c {
+      '\'' => {
+        in_word = true;
+        for c in chars.by_ref() {
+          if c == '\'' {
+            break;
+          }
+          current.push(c);
+        }
+      }
+      '"' => {
+        in_word = true;
+        while let Some(c) = chars.next() {
+          match c {
+            '"' => break,
+            '\\' => match chars.next() {
+              Some(next @ ('"' | '\\' | '$' | '`')) => current.push(next),
+              Some(next) => {
+                current.push('\\');
+                current.push(next);
+              }
+              None => current.push('\\'),
+            },
+            c => current.push(c),
+          }
+        }
+      }
+      '\\' => {
+        in_word = true;
+        if let Some(next) = chars.next() {
+          current.push(next);
+        }
+      }
+      c if c.is_whitespace() => {
+        if in_word {
+          args.push(std::mem::take(&mut current));
+          in_word = false;
+        }
+      }
+      c => {
+        in_word = true;
+        current.push(c);
+      }
+    }
+  }
+
+  if in_word {
+    args.push(current);
+  }
+  args
+}
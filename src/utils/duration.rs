@@ -0,0 +1,28 @@
+use crate::error::Error;
+use std::time::{Duration, Instant};
+
+/// Parse durations like `500ms`, `10s`, `10m` or `1h`, a bare number is in seconds
+pub fn parse(s: &str) -> Result<Duration, Error> {
+  let s = s.trim();
+  let index = s
+    .find(|c: char| !c.is_ascii_digit() && c != '.')
+    .unwrap_or(s.len());
+  let (value, unit) = s.split_at(index);
+
+  let invalid = || Error::Command(format!("Invalid duration \"{}\"", s));
+  let value: f64 = value.parse().map_err(|_| invalid())?;
+
+  let seconds = match unit {
+    "ms" => value / 1000.0,
+    "" | "s" => value,
+    "m" => value * 60.0,
+    "h" => value * 3600.0,
+    _ => return Err(invalid()),
+  };
+
+  // Timeouts and grace periods are added to the current time, they must fit in an instant
+  match Duration::try_from_secs_f64(seconds) {
+    Ok(duration) if Instant::now().checked_add(duration).is_some() => Ok(duration),
+    _ => Err(Error::Command(format!("Duration \"{}\" is too long", s))),
+  }
+}
@@ -0,0 +1,88 @@
+use crate::error::Error;
+use globset::{GlobBuilder, GlobMatcher};
+use std::path::{Path, PathBuf};
+
+/// Whether the string contains glob syntax, like `*`, `**`, `?`, `[ab]` or `{a,b}`
+pub fn is_pattern(s: &str) -> bool {
+  s.contains(['*', '?', '[', '{'])
+}
+
+/// Matcher of a `/` separated pattern, `*` stays within a directory and `**` spans any number
+pub fn matcher(pattern: &str) -> Result<GlobMatcher, Error> {
+  let glob = GlobBuilder::new(pattern)
+    .literal_separator(true)
+    .build()
+    .map_err(|e| Error::Command(e.to_string()))?;
+  Ok(glob.compile_matcher())
+}
+
+/// Split a pattern into the directory without wildcard it starts from and the rest
+pub fn split(base: &Path, pattern: &str) -> (PathBuf, String) {
+  let pattern = pattern.trim_start_matches("./");
+  let segments: Vec<&str> = pattern.split('/').collect();
+  let literal = segments.iter().take_while(|s| !is_pattern(s)).count();
+
+  let root = base.join(segments[..literal].join("/"));
+  (root, segments[literal..].join("/"))
+}
+
+/// Files matching the pattern relative to the base directory, sorted.
+/// A directory given without wildcard stands for every file below it.
+pub fn expand(base: &Path, pattern: &str) -> Result<Vec<PathBuf>, Error> {
+  let (root, rest) = split(base, pattern);
+
+  let mut files = Vec::new();
+  if rest.is_empty() {
+    if root.is_dir() {
+      walk(&root, &root, &matcher("**")?, &mut files);
+    } else if root.exists() {
+      files.push(root);
+    }
+  } else {
+    walk(&root, &root, &matcher(&rest)?, &mut files);
+  }
+
+  files.sort();
+  Ok(files)
+}
+
+/// Whether a path, existing or not, is matched by the pattern relative to the base directory.
+/// An invalid pattern matches nothing.
+pub fn contains(base: &Path, pattern: &str, path: &Path) -> bool {
+  let (root, rest) = split(base, pattern);
+  let relative = match path.strip_prefix(&root) {
+    Ok(relative) => relative,
+    Err(_) => return false,
+  };
+
+  rest.is_empty() || matcher(&rest).is_ok_and(|matcher| matcher.is_match(relative))
+}
+
+fn walk(root: &Path, dir: &Path, matcher: &GlobMatcher, files: &mut Vec<PathBuf>) {
+  let entries = match std::fs::read_dir(dir) {
+    Ok(entries) => entries,
+    Err(_) => return,
+  };
+
+  for entry in entries.flatten() {
+    let path = entry.path();
+    let file_type = match entry.file_type() {
+      Ok(file_type) => file_type,
+      Err(_) => continue,
+    };
+    if file_type.is_dir() {
+      walk(root, &path, matcher, files);
+      continue;
+    }
+    // Symlinked directories are not followed, they could loop back to a parent
+    if file_type.is_symlink() && path.is_dir() {
+      continue;
+    }
+
+    if let Ok(relative) = path.strip_prefix(root) {
+      if matcher.is_match(relative) {
+        files.push(path);
+      }
+    }
+  }
+}
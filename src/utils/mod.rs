@@ -1,3 +1,8 @@
 pub mod argv;
+pub mod duration;
 pub mod fs;
+pub mod glob;
 pub mod path;
+pub mod platform;
+pub mod timer;
+pub mod watch;
@@ -1,3 +1,11 @@
+#![allow(clippy::needless_return)]
+
+use fnv::FnvHasher;
+use std::hash::Hasher;
+
+/// Characters of a name kept in its file name
+const MAX_NAME: usize = 64;
+
 pub trait PathExt {
   fn normalize<S>(&self) -> S
   where
@@ -91,3 +99,22 @@ impl PathExt for std::path::Path {
     })
   }
 }
+
+/// Name usable as a file name, characters out of `[a-zA-Z0-9._-]` are replaced with `_`.
+/// A short hash of the whole name follows, names mapped alike get their own file.
+pub fn file_name(name: &str) -> String {
+  let mut file_name: String = name
+    .chars()
+    .take(MAX_NAME)
+    .map(|c| match c {
+      'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' | '.' => c,
+      _ => '_',
+    })
+    .collect();
+
+  let mut hasher = FnvHasher::default();
+  hasher.write(name.as_bytes());
+  file_name.push('-');
+  file_name.push_str(&format!("{:016x}", hasher.finish())[..8]);
+  file_name
+}
@@ -0,0 +1,57 @@
+/// Name of the platform wk runs on: `linux`, `macos`, `windows`...
+pub fn current() -> &'static str {
+  std::env::consts::OS
+}
+
+/// Accept the common aliases of a platform name
+pub fn normalize(name: &str) -> String {
+  match name.to_lowercase().as_str() {
+    "win" | "win32" | "win64" => "windows".to_string(),
+    "osx" | "darwin" | "mac" => "macos".to_string(),
+    name => name.to_string(),
+  }
+}
+
+/// Whether the platform name designates the current platform, `unix` covers every unix
+pub fn matches(name: &str) -> bool {
+  let name = normalize(name);
+  name == current() || (name == "unix" && cfg!(unix))
+}
+
+/// Whether one of the platforms designates the current platform
+pub fn matches_any(names: &[String]) -> bool {
+  names.iter().any(|name| matches(name))
+}
+
+/// Platforms a task is restricted to, every list added has to match
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Platforms(Vec<Vec<String>>);
+
+impl Platforms {
+  pub fn add<I, S>(&mut self, platforms: I)
+  where
+    I: IntoIterator<Item = S>,
+    S: Into<String>,
+  {
+    let platforms: Vec<String> = platforms.into_iter().map(|s| s.into()).collect();
+    if !platforms.is_empty() {
+      self.0.push(platforms);
+    }
+  }
+
+  pub fn lists(&self) -> &Vec<Vec<String>> {
+    &self.0
+  }
+
+  /// Names of the restriction not met first, or else of the last one
+  pub fn names(&self) -> Vec<String> {
+    match self.0.iter().find(|p| !matches_any(p)) {
+      Some(platforms) => platforms.clone(),
+      None => self.0.last().cloned().unwrap_or_default(),
+    }
+  }
+
+  pub fn is_available(&self) -> bool {
+    self.0.iter().all(|p| matches_any(p))
+  }
+}
@@ -0,0 +1,46 @@
+use futures::channel::oneshot;
+use std::{
+  future::Future,
+  thread,
+  time::{Duration, Instant},
+};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Resolve after the given duration without blocking the executor
+pub fn delay(duration: Duration) -> impl Future<Output = ()> {
+  let (sender, receiver) = oneshot::channel::<()>();
+
+  thread::spawn(move || {
+    thread::sleep(duration);
+    let _ = sender.send(());
+  });
+
+  async move {
+    let _ = receiver.await;
+  }
+}
+
+/// Like `delay`, resolved early once `stop` returns true
+pub fn delay_until<F>(duration: Duration, stop: F) -> impl Future<Output = ()>
+where
+  F: Fn() -> bool + Send + 'static,
+{
+  let (sender, receiver) = oneshot::channel::<()>();
+
+  thread::spawn(move || {
+    let start = Instant::now();
+    while !stop() {
+      let left = duration.saturating_sub(start.elapsed());
+      if left.is_zero() {
+        break;
+      }
+      thread::sleep(left.min(POLL_INTERVAL));
+    }
+    let _ = sender.send(());
+  });
+
+  async move {
+    let _ = receiver.await;
+  }
+}
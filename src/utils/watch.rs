@@ -0,0 +1,190 @@
+use super::glob;
+use crate::supervisor;
+use futures::channel::mpsc;
+use notify::{Config, Event, PollWatcher, RecommendedWatcher, RecursiveMode, Watcher as _};
+use std::{
+  path::{Component, Path, PathBuf},
+  sync::mpsc as channel,
+  thread,
+  time::Duration,
+};
+
+/// Quiet period after a change before the changed files are reported
+pub const DEBOUNCE: Duration = Duration::from_millis(300);
+
+const TICK: Duration = Duration::from_millis(200);
+
+type Events = channel::Receiver<notify::Result<Event>>;
+
+/// Report changes of the files matching the patterns, debounced.
+/// The channel is closed once wk is interrupted.
+pub fn watch(base: PathBuf, patterns: Vec<String>) -> mpsc::UnboundedReceiver<Vec<PathBuf>> {
+  let (sender, receiver) = mpsc::unbounded();
+
+  thread::spawn(move || {
+    let mut watcher = Watcher::new(&base, &patterns);
+
+    while supervisor::signal().is_none() {
+      let mut changes = watcher.changes(TICK);
+      if changes.is_empty() {
+        continue;
+      }
+
+      loop {
+        let more = watcher.changes(DEBOUNCE);
+        if more.is_empty() {
+          break;
+        }
+        changes.extend(more);
+      }
+
+      changes.sort();
+      changes.dedup();
+      if sender.unbounded_send(changes).is_err() {
+        return;
+      }
+    }
+  });
+
+  receiver
+}
+
+struct Watcher {
+  base: PathBuf,
+  patterns: Vec<String>,
+  // Events are sent as long as the backend is alive
+  backend: Option<Box<dyn notify::Watcher + Send>>,
+  events: Events,
+  polling: bool,
+}
+
+impl Watcher {
+  fn new(base: &Path, patterns: &[String]) -> Self {
+    let (sender, events) = channel::channel();
+    let mut watcher = Watcher {
+      base: base.to_path_buf(),
+      patterns: patterns.to_vec(),
+      backend: None,
+      events,
+      polling: false,
+    };
+
+    match RecommendedWatcher::new(sender, Config::default()) {
+      Ok(backend) => {
+        watcher.backend = Some(Box::new(backend));
+        if !watcher.watch_roots() {
+          info!("Cannot watch every directory, polling files instead");
+          watcher.poll();
+        }
+      }
+      Err(e) => {
+        verbose!("Cannot watch files ({}), polling them instead", e);
+        watcher.poll();
+      }
+    }
+    watcher
+  }
+
+  /// Replace the backend with one polling the files, events of the previous one are dropped
+  fn poll(&mut self) {
+    let (sender, events) = channel::channel();
+    let config = Config::default().with_poll_interval(TICK);
+    self.events = events;
+    self.polling = true;
+    self.backend = match PollWatcher::new(sender, config) {
+      Ok(backend) => Some(Box::new(backend)),
+      Err(e) => {
+        info!("Cannot poll files: {}", e);
+        None
+      }
+    };
+    self.watch_roots();
+  }
+
+  /// Watch the directories the patterns start from, whether every one of them is watched
+  fn watch_roots(&mut self) -> bool {
+    let backend = match &mut self.backend {
+      Some(backend) => backend,
+      None => return false,
+    };
+
+    let mut complete = true;
+    for (root, mode) in roots(&self.base, &self.patterns) {
+      // A directory removed in the meantime is not missed
+      if backend.watch(&root, mode).is_err() && root.is_dir() {
+        complete = false;
+      }
+    }
+    complete
+  }
+
+  /// Files matching the patterns changed within the timeout
+  fn changes(&mut self, timeout: Duration) -> Vec<PathBuf> {
+    let mut events: Vec<notify::Result<Event>> = Vec::new();
+    if let Ok(event) = self.events.recv_timeout(timeout) {
+      events.push(event);
+      events.extend(self.events.try_iter());
+    }
+
+    let mut paths: Vec<PathBuf> = Vec::new();
+    let mut missed = false;
+    for event in events {
+      match event {
+        Ok(event) => {
+          missed |= event.need_rescan();
+          paths.extend(event.paths);
+        }
+        Err(e) => {
+          verbose!("Watch error: {}", e);
+          missed = true;
+        }
+      }
+    }
+
+    // Changes the backend could not keep up with would be missed
+    if missed && !self.polling {
+      info!("Cannot watch every change, polling files instead");
+      self.poll();
+    }
+
+    paths
+      .into_iter()
+      .filter(|path| !is_hidden(&self.base, path))
+      .filter(|path| {
+        let path = path.as_path();
+        self
+          .patterns
+          .iter()
+          .any(|pattern| glob::contains(&self.base, pattern, path))
+      })
+      .collect()
+  }
+}
+
+/// Directories to watch, with their subdirectories or not
+fn roots(base: &Path, patterns: &[String]) -> Vec<(PathBuf, RecursiveMode)> {
+  let mut roots: Vec<(PathBuf, RecursiveMode)> = patterns
+    .iter()
+    .filter_map(|pattern| {
+      let (root, _) = glob::split(base, pattern);
+      if root.is_dir() {
+        return Some((root, RecursiveMode::Recursive));
+      }
+      root
+        .parent()
+        .filter(|parent| parent.is_dir())
+        .map(|parent| (parent.to_path_buf(), RecursiveMode::NonRecursive))
+    })
+    .collect();
+  roots.dedup();
+  roots
+}
+
+/// Changes of the repository and of the wk cache are not the ones of the watched files
+fn is_hidden(base: &Path, path: &Path) -> bool {
+  let relative = path.strip_prefix(base).unwrap_or(path);
+  relative.components().any(|component| match component {
+    Component::Normal(name) => name == ".git" || name == ".wk",
+    _ => false,
+  })
+}